@@ -0,0 +1,6 @@
+#![no_main]
+
+use crdt_list::{causal_tree_dumb_impl::CausalTreeImpl, test, test::Action};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: Vec<Action>| { test::test_with_actions::<CausalTreeImpl>(5, 100, data) });
@@ -0,0 +1,54 @@
+//! This mod impl the Causal Tree described in Victor Grishchenko's Causal Trees: Towards Real-Time Read-Write Hypertext
+//!
+//! Every op references its parent, i.e. the op it was inserted after.
+//! The list is the depth-first traversal of the tree, where the children of
+//! an op are ordered by descending timestamp, then by descending [ListCrdt::cmp_id].
+
+use std::cmp::Ordering;
+
+use crate::crdt::{GetOp, ListCrdt, OpSet};
+
+/// For CausalTree iter should iterate from `start` to the end of the list, `to` is always `None`
+pub trait CausalTree: ListCrdt {
+    type Timestamp: Ord;
+    fn parent(op: &Self::OpUnit) -> Option<Self::OpId>;
+    /// e.g. lamport, the ops with the same timestamp are ordered by [ListCrdt::cmp_id]
+    fn timestamp(op: &Self::OpUnit) -> Self::Timestamp;
+    fn insert_after(container: &mut Self::Container, left: Option<Self::OpId>, op: Self::OpUnit);
+}
+
+pub fn integrate<T: CausalTree>(container: &mut T::Container, to_insert: T::OpUnit) {
+    if T::integrated(container, T::id(&to_insert)) {
+        return;
+    }
+
+    let parent = T::parent(&to_insert);
+    let timestamp = T::timestamp(&to_insert);
+    let mut left = parent;
+    // the siblings before `to_insert` and their descendants
+    let mut skipped = T::Set::default();
+    for op in T::iter(container, parent, None) {
+        let op = op.get_op_ref();
+        if parent.is_some() && T::contains(op, parent.unwrap()) {
+            continue;
+        }
+
+        let op_parent = T::parent(op);
+        if op_parent == parent {
+            let order = T::timestamp(op)
+                .cmp(&timestamp)
+                .then_with(|| T::cmp_id(op, &to_insert));
+            if order == Ordering::Less {
+                break;
+            }
+        } else if op_parent.is_none() || !skipped.contain(op_parent.unwrap()) {
+            // left the subtree of `parent`
+            break;
+        }
+
+        skipped.insert(op);
+        left = Some(T::id(op));
+    }
+
+    T::insert_after(container, left, to_insert);
+}
@@ -0,0 +1,334 @@
+use std::marker::PhantomData;
+
+use crate::dumb_common::peer_id;
+pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl, PeerId};
+use crate::id_span::IdSpanSet;
+use crate::tie_break::{Ascending, TieBreak};
+use crate::{causal_tree, crdt::ListCrdt, local::Tombstones, test::TestFramework};
+
+pub struct CausalTreeImpl<P = Ascending>(PhantomData<P>);
+impl<P: TieBreak<PeerId>> CausalTreeImpl<P> {
+    fn container_contains(
+        container: &<Self as ListCrdt>::Container,
+        op_id: Option<<Self as ListCrdt>::OpId>,
+    ) -> bool {
+        container.contains(op_id)
+    }
+}
+
+impl<P: TieBreak<PeerId>> ListCrdt for CausalTreeImpl<P> {
+    type OpUnit = Op;
+
+    type OpId = OpId;
+
+    type Container = Container;
+
+    type Pos = usize;
+
+    type Cursor<'a> = Cursor<'a>;
+
+    type Set = OpSetImpl;
+
+    type Iterator<'a> = Iter<'a>;
+
+    fn iter(
+        container: &Self::Container,
+        from: Option<Self::OpId>,
+        to: Option<Self::OpId>,
+    ) -> Self::Iterator<'_> {
+        Iter {
+            arr: &container.content,
+            index: 0,
+            start: from,
+            end: to,
+            done: false,
+            started: false,
+            exclude_end: false,
+        }
+    }
+
+    fn id(op: &Self::OpUnit) -> Self::OpId {
+        op.id
+    }
+
+    fn cmp_id(op_a: &Self::OpUnit, op_b: &Self::OpUnit) -> std::cmp::Ordering {
+        P::cmp_client(&op_a.id.client_id, &op_b.id.client_id)
+            .then(op_a.id.clock.cmp(&op_b.id.clock))
+    }
+
+    fn contains(op: &Self::OpUnit, id: Self::OpId) -> bool {
+        op.id == id
+    }
+
+    fn integrated(container: &Self::Container, id: Self::OpId) -> bool {
+        container.contains(Some(id))
+    }
+}
+
+impl<P: TieBreak<PeerId>> Tombstones for CausalTreeImpl<P> {
+    fn get(container: &Self::Container, id: OpId) -> Option<&Op> {
+        container.content.get(id)
+    }
+
+    fn visible_id(container: &Self::Container, index: usize) -> Option<OpId> {
+        container.content.visible_id(index)
+    }
+
+    fn next_id(container: &Self::Container, id: Option<OpId>) -> Option<OpId> {
+        container.content.next_id(id)
+    }
+}
+
+impl<P: TieBreak<PeerId>> causal_tree::CausalTree for CausalTreeImpl<P> {
+    type Timestamp = u32;
+
+    fn parent(op: &Self::OpUnit) -> Option<Self::OpId> {
+        op.left
+    }
+
+    fn timestamp(op: &Self::OpUnit) -> Self::Timestamp {
+        op.lamport
+    }
+
+    fn insert_after(container: &mut Self::Container, left: Option<Self::OpId>, op: Self::OpUnit) {
+        match left {
+            Some(left) => {
+                let pos = container.content.pos_of(left).unwrap();
+                container.content.insert(pos + 1, op);
+            }
+            None => {
+                container.content.insert(0, op);
+            }
+        }
+    }
+}
+
+impl<P: TieBreak<PeerId>> TestFramework for CausalTreeImpl<P> {
+    fn is_content_eq(a: &Self::Container, b: &Self::Container) -> bool {
+        a.content.eq(&b.content)
+    }
+
+    fn fork(container: &Self::Container, id: usize) -> Self::Container {
+        container.fork(peer_id(id))
+    }
+
+    fn new_container(id: usize) -> Self::Container {
+        Container::new(peer_id(id))
+    }
+
+    fn new_op(container: &mut Self::Container, pos: usize) -> Self::OpUnit {
+        let insert_pos = pos % (container.content.len() + 1);
+        let left = if insert_pos == 0 {
+            None
+        } else {
+            Some(container.content[insert_pos - 1].id)
+        };
+
+        let ans = Op {
+            id: OpId {
+                client_id: container.id,
+                clock: container.max_clock,
+            },
+            left,
+            right: None,
+            degree: 0,
+            deleted: false,
+            lamport: container
+                .content
+                .iter()
+                .map(|x| x.lamport + 1)
+                .max()
+                .unwrap_or(0),
+        };
+
+        container.max_clock += 1;
+        ans
+    }
+
+    type DeleteOp = IdSpanSet<PeerId>;
+
+    fn new_del_op(container: &Self::Container, mut pos: usize, mut len: usize) -> Self::DeleteOp {
+        let content_len = container.content.real_len();
+        let mut deleted = IdSpanSet::new();
+        if content_len == 0 {
+            return deleted;
+        }
+
+        pos %= content_len;
+        len = std::cmp::min(len, content_len - pos);
+        for op in container.content.iter_real_from(pos).take(len) {
+            deleted.insert(op.id.client_id, op.id.clock);
+        }
+
+        deleted
+    }
+
+    fn integrate_delete_op(container: &mut Self::Container, delete_set: Self::DeleteOp) {
+        container.content.delete(&delete_set);
+        container.deleted = container.deleted.union(&delete_set);
+    }
+
+    fn can_integrate_delete_op(container: &Self::Container, op: &Self::DeleteOp) -> bool {
+        container.contains_all(op)
+    }
+
+    fn integrate(container: &mut Self::Container, op: Self::OpUnit) {
+        if container.contains(Some(op.id)) {
+            return;
+        }
+
+        causal_tree::integrate::<Self>(container, op.clone());
+        container.advance(&op);
+    }
+
+    fn can_integrate(container: &Self::Container, op: &Self::OpUnit) -> bool {
+        Self::container_contains(container, op.left)
+            && (op.id.clock == 0
+                || Self::container_contains(
+                    container,
+                    Some(OpId {
+                        client_id: op.id.client_id,
+                        clock: op.id.clock - 1,
+                    }),
+                ))
+    }
+}
+
+#[cfg(test)]
+mod causal_tree_impl_test {
+    use super::*;
+    use crate::test::Action::*;
+
+    #[test]
+    fn siblings_are_ordered_by_descending_timestamp() {
+        let mut a = <CausalTreeImpl>::new_container(0);
+        let mut b = <CausalTreeImpl>::new_container(1);
+        let root = <CausalTreeImpl>::new_op(&mut a, 0);
+        <CausalTreeImpl>::integrate(&mut a, root.clone());
+        <CausalTreeImpl>::integrate(&mut b, root.clone());
+        // a types two ops after root, b inserts one concurrently right after root
+        let a_1 = <CausalTreeImpl>::new_op(&mut a, 1);
+        <CausalTreeImpl>::integrate(&mut a, a_1.clone());
+        let a_2 = <CausalTreeImpl>::new_op(&mut a, 2);
+        <CausalTreeImpl>::integrate(&mut a, a_2.clone());
+        let b_1 = <CausalTreeImpl>::new_op(&mut b, 1);
+        <CausalTreeImpl>::integrate(&mut b, b_1.clone());
+        <CausalTreeImpl>::integrate(&mut a, b_1.clone());
+        <CausalTreeImpl>::integrate(&mut b, a_1.clone());
+        <CausalTreeImpl>::integrate(&mut b, a_2.clone());
+
+        assert_eq!(a.content, b.content);
+        // a_1 and b_1 have the same lamport, the one with the greater peer id comes first
+        let ids: Vec<_> = a.content.iter().map(|x| x.id).collect();
+        if b_1.id.client_id > a_1.id.client_id {
+            assert_eq!(ids, vec![root.id, b_1.id, a_1.id, a_2.id]);
+        } else {
+            assert_eq!(ids, vec![root.id, a_1.id, a_2.id, b_1.id]);
+        }
+    }
+
+    #[test]
+    fn simple() {
+        crate::test::test_actions::<CausalTreeImpl>(
+            2,
+            vec![
+                NewOp {
+                    client_id: 0,
+                    pos: 0,
+                },
+                NewOp {
+                    client_id: 1,
+                    pos: 0,
+                },
+                Sync { from: 0, to: 1 },
+                NewOp {
+                    client_id: 1,
+                    pos: 1,
+                },
+                NewOp {
+                    client_id: 0,
+                    pos: 1,
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn run() {
+        for seed in 0..100 {
+            crate::test::test::<CausalTreeImpl>(seed, 2, 1000);
+        }
+    }
+
+    #[test]
+    fn run_n() {
+        for n in 2..10 {
+            crate::test::test::<CausalTreeImpl>(123, n, 10000);
+        }
+    }
+
+    #[test]
+    fn fork_merge() {
+        for seed in 0..100 {
+            crate::test::test_fork_merge::<CausalTreeImpl>(seed, 3, 50);
+        }
+    }
+
+    #[test]
+    fn exhaustive() {
+        crate::test::explore::<CausalTreeImpl>(2, 4, 2);
+        crate::test::explore::<CausalTreeImpl>(3, 3, 2);
+    }
+
+    #[test]
+    fn delivery_orders() {
+        for seed in 0..20 {
+            crate::test::test_delivery_orders::<CausalTreeImpl>(seed, 3, 200, 10);
+        }
+    }
+
+    #[test]
+    fn lossy_network() {
+        for seed in 0..10 {
+            crate::network::test_network::<CausalTreeImpl>(seed, 4, 200, Default::default());
+        }
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        for seed in 0..20 {
+            crate::test::test_duplicates::<CausalTreeImpl>(seed, 3, 300);
+        }
+    }
+
+    #[test]
+    fn tie_break_policies() {
+        use crate::{
+            dumb_common::peer_id,
+            test::tie,
+            tie_break::{Ascending, Descending, Hashed, Priority},
+        };
+        type Server = Priority<{ peer_id(1) }>;
+        // peer 0 has the greater id, but peer 1 wins the tie
+        assert_eq!(
+            tie::<CausalTreeImpl<Server>>(0, 1),
+            tie::<CausalTreeImpl<Descending>>(0, 1)
+        );
+        assert_ne!(
+            tie::<CausalTreeImpl<Server>>(0, 1),
+            tie::<CausalTreeImpl<Ascending>>(0, 1)
+        );
+        for seed in 0..30 {
+            crate::test::test::<CausalTreeImpl<Descending>>(seed, 3, 300);
+            crate::test::test::<CausalTreeImpl<Server>>(seed, 3, 300);
+            crate::test::test::<CausalTreeImpl<Hashed>>(seed, 5, 300);
+            crate::test::test_fork_merge::<CausalTreeImpl<Hashed>>(seed, 3, 50);
+        }
+    }
+
+    use ctor::ctor;
+    #[ctor]
+    fn init_color_backtrace() {
+        color_backtrace::install();
+    }
+}
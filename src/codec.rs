@@ -0,0 +1,401 @@
+//! A compact binary encoding for the ops and the messages sent between replicas.
+//!
+//! Integers are LEB128 varints, since clocks, lengths and client ids are mostly small.
+//! Sequences are prefixed by their length and enums by a one-byte tag. Decoding never
+//! trusts the input: truncated or malformed bytes give a [DecodeError] instead of a panic.
+
+use std::fmt::Display;
+
+use crate::{
+    id_span::IdSpanSet,
+    list_doc::{DeleteOp, InsertOp, ListOp, OpId},
+    peer::Version,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    /// a varint that doesn't fit in its type, or a clock after `usize::MAX`
+    Overflow,
+    InvalidTag(u8),
+    InvalidChar(u32),
+    InvalidUtf8,
+    /// the value ends before the input
+    TrailingBytes,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::Overflow => write!(f, "varint overflow"),
+            DecodeError::InvalidTag(tag) => write!(f, "invalid tag {}", tag),
+            DecodeError::InvalidChar(x) => write!(f, "invalid char {:#x}", x),
+            DecodeError::InvalidUtf8 => write!(f, "invalid utf-8"),
+            DecodeError::TrailingBytes => write!(f, "trailing bytes"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub trait Encode: Sized {
+    fn encode_to(&self, buf: &mut Vec<u8>);
+
+    /// Decode a value from the start of `buf` and advance it
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError>;
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_to(&mut buf);
+        buf
+    }
+
+    /// Decode a value that takes the whole of `bytes`
+    fn decode(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = Self::decode_from(&mut bytes)?;
+        match bytes.is_empty() {
+            true => Ok(value),
+            false => Err(DecodeError::TrailingBytes),
+        }
+    }
+}
+
+pub fn read_u8(buf: &mut &[u8]) -> Result<u8, DecodeError> {
+    let (&byte, rest) = buf.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+    *buf = rest;
+    Ok(byte)
+}
+
+impl Encode for u64 {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        let mut x = *self;
+        while x >= 0x80 {
+            buf.push((x as u8) | 0x80);
+            x >>= 7;
+        }
+        buf.push(x as u8);
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut ans = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = read_u8(buf)?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                return Err(DecodeError::Overflow);
+            }
+
+            ans |= bits << shift;
+            if byte < 0x80 {
+                return Ok(ans);
+            }
+        }
+
+        Err(DecodeError::Overflow)
+    }
+}
+
+impl Encode for u128 {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        let mut x = *self;
+        while x >= 0x80 {
+            buf.push((x as u8) | 0x80);
+            x >>= 7;
+        }
+        buf.push(x as u8);
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let mut ans = 0u128;
+        for shift in (0..128).step_by(7) {
+            let byte = read_u8(buf)?;
+            let bits = (byte & 0x7f) as u128;
+            if shift == 126 && bits > 3 {
+                return Err(DecodeError::Overflow);
+            }
+
+            ans |= bits << shift;
+            if byte < 0x80 {
+                return Ok(ans);
+            }
+        }
+
+        Err(DecodeError::Overflow)
+    }
+}
+
+impl Encode for usize {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode_to(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        usize::try_from(u64::decode_from(buf)?).map_err(|_| DecodeError::Overflow)
+    }
+}
+
+impl Encode for u32 {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode_to(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        u32::try_from(u64::decode_from(buf)?).map_err(|_| DecodeError::Overflow)
+    }
+}
+
+impl Encode for char {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode_to(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let x = u32::decode_from(buf)?;
+        char::from_u32(x).ok_or(DecodeError::InvalidChar(x))
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.len().encode_to(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = usize::decode_from(buf)?;
+        if buf.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let (bytes, rest) = buf.split_at(len);
+        *buf = rest;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            None => buf.push(0),
+            Some(x) => {
+                buf.push(1);
+                x.encode_to(buf);
+            }
+        }
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_u8(buf)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode_from(buf)?)),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.len().encode_to(buf);
+        for x in self.iter() {
+            x.encode_to(buf);
+        }
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = usize::decode_from(buf)?;
+        // every value takes at least one byte, so a bogus length can't allocate much
+        let mut ans = Vec::with_capacity(std::cmp::min(len, buf.len()));
+        for _ in 0..len {
+            ans.push(T::decode_from(buf)?);
+        }
+
+        Ok(ans)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.0.encode_to(buf);
+        self.1.encode_to(buf);
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok((A::decode_from(buf)?, B::decode_from(buf)?))
+    }
+}
+
+impl Encode for OpId {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.client_id.encode_to(buf);
+        self.clock.encode_to(buf);
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(OpId {
+            client_id: u128::decode_from(buf)?,
+            clock: usize::decode_from(buf)?,
+        })
+    }
+}
+
+impl<C: Encode + Ord + Clone> Encode for IdSpanSet<C> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        IdSpanSet::encode(self).encode_to(buf)
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let encoded: Vec<(C, Vec<(usize, usize)>)> = Encode::decode_from(buf)?;
+        IdSpanSet::decode(&encoded).ok_or(DecodeError::Overflow)
+    }
+}
+
+impl<P: Encode + Ord + Clone> Encode for Version<P> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.len().encode_to(buf);
+        for (peer, end) in self.iter() {
+            peer.encode_to(buf);
+            end.encode_to(buf);
+        }
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let pairs: Vec<(P, usize)> = Encode::decode_from(buf)?;
+        Ok(pairs.into_iter().collect())
+    }
+}
+
+impl<V: Encode> Encode for InsertOp<V> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.id.encode_to(buf);
+        self.lamport.encode_to(buf);
+        self.left.encode_to(buf);
+        self.right.encode_to(buf);
+        self.value.encode_to(buf);
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(InsertOp {
+            id: OpId::decode_from(buf)?,
+            lamport: u32::decode_from(buf)?,
+            left: Option::decode_from(buf)?,
+            right: Option::decode_from(buf)?,
+            value: V::decode_from(buf)?,
+        })
+    }
+}
+
+impl Encode for DeleteOp {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.id.encode_to(buf);
+        self.lamport.encode_to(buf);
+        self.targets.encode_to(buf);
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(DeleteOp {
+            id: OpId::decode_from(buf)?,
+            lamport: u32::decode_from(buf)?,
+            targets: IdSpanSet::decode_from(buf)?,
+        })
+    }
+}
+
+impl<V: Encode> Encode for ListOp<V> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            ListOp::Insert(op) => {
+                buf.push(0);
+                op.encode_to(buf);
+            }
+            ListOp::Delete(op) => {
+                buf.push(1);
+                op.encode_to(buf);
+            }
+        }
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_u8(buf)? {
+            0 => Ok(ListOp::Insert(InsertOp::decode_from(buf)?)),
+            1 => Ok(ListOp::Delete(DeleteOp::decode_from(buf)?)),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod codec_test {
+    use super::*;
+    use crate::list_doc::{Fugue, ListDoc};
+
+    #[test]
+    fn varints() {
+        for x in [0u64, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let bytes = x.encode();
+            assert_eq!(u64::decode(&bytes), Ok(x));
+        }
+        assert_eq!(127u64.encode().len(), 1);
+        assert_eq!(128u64.encode().len(), 2);
+        assert_eq!(u64::MAX.encode().len(), 10);
+        assert_eq!(u64::decode(&[0xff; 11]), Err(DecodeError::Overflow));
+        assert_eq!(u32::decode(&u64::MAX.encode()), Err(DecodeError::Overflow));
+        for x in [0u128, 1 << 64, u128::MAX] {
+            assert_eq!(u128::decode(&x.encode()), Ok(x));
+        }
+        assert_eq!(u128::MAX.encode().len(), 19);
+        assert_eq!(u128::decode(&[0xff; 20]), Err(DecodeError::Overflow));
+    }
+
+    #[test]
+    fn ops_round_trip() {
+        let mut doc = ListDoc::<Fugue, String>::new(3);
+        let mut ops = doc.insert(0, ["a".to_string(), "bé".to_string(), "".to_string()]);
+        ops.extend(doc.delete(0..2));
+        for op in ops {
+            assert_eq!(ListOp::decode(&op.encode()), Ok(op));
+        }
+    }
+
+    #[test]
+    fn malformed_input() {
+        let op = ListDoc::<Fugue, char>::new(0).insert(0, ['x']).remove(0);
+        let bytes = op.encode();
+        for len in 0..bytes.len() {
+            assert_eq!(
+                ListOp::<char>::decode(&bytes[..len]),
+                Err(DecodeError::UnexpectedEnd)
+            );
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            ListOp::<char>::decode(&trailing),
+            Err(DecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            ListOp::<char>::decode(&[7]),
+            Err(DecodeError::InvalidTag(7))
+        );
+        assert_eq!(
+            char::decode(&0xd800u32.encode()),
+            Err(DecodeError::InvalidChar(0xd800))
+        );
+        // a huge length doesn't allocate
+        assert_eq!(
+            Vec::<u32>::decode(&u64::MAX.encode()),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        // the second span of the delete ends after usize::MAX
+        let mut bytes = vec![1];
+        OpId {
+            client_id: 0,
+            clock: 0,
+        }
+        .encode_to(&mut bytes);
+        0u32.encode_to(&mut bytes);
+        vec![(7u128, vec![(5usize, 1usize), (usize::MAX, 1)])].encode_to(&mut bytes);
+        assert_eq!(ListOp::<char>::decode(&bytes), Err(DecodeError::Overflow));
+    }
+}
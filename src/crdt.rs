@@ -8,7 +8,19 @@ pub trait OpSet<Op, OpId>: Default {
 
 pub trait GetOp {
     type Target;
-    fn get_op(&self) -> Self::Target;
+    /// The algorithms only read the op under the cursor, so it doesn't need to be cloned
+    fn get_op_ref(&self) -> &Self::Target;
+    fn get_op(&self) -> Self::Target
+    where
+        Self::Target: Clone,
+    {
+        self.get_op_ref().clone()
+    }
+}
+
+pub trait GetPos {
+    type Pos;
+    fn get_pos(&self) -> Self::Pos;
 }
 
 pub trait ListCrdt {
@@ -16,7 +28,11 @@ pub trait ListCrdt {
     type OpId: Eq + Copy + Debug;
     type Container: Debug;
     type Set: OpSet<Self::OpUnit, Self::OpId>;
-    type Cursor<'a>: GetOp<Target = Self::OpUnit>;
+    /// A handle to the position of an op. It only needs to stay valid until the container is modified.
+    type Pos: Copy;
+    /// Cursors only read the container. To insert at a cursor, get its [GetPos::get_pos] and
+    /// pass it to the algorithm's insert method after the iteration is done.
+    type Cursor<'a>: GetOp<Target = Self::OpUnit> + GetPos<Pos = Self::Pos>;
     type Iterator<'a>: Iterator<Item = Self::Cursor<'a>>
     where
         <Self as ListCrdt>::OpUnit: 'a,
@@ -24,11 +40,14 @@ pub trait ListCrdt {
 
     /// inclusive end
     fn iter(
-        container: &mut Self::Container,
+        container: &Self::Container,
         from: Option<Self::OpId>,
         to: Option<Self::OpId>,
     ) -> Self::Iterator<'_>;
     fn id(op: &Self::OpUnit) -> Self::OpId;
     fn cmp_id(op_a: &Self::OpUnit, op_b: &Self::OpUnit) -> Ordering;
     fn contains(op: &Self::OpUnit, id: Self::OpId) -> bool;
+    /// Whether the op `id` is integrated into `container`. The integrate functions ignore
+    /// such ops, so delivering an op twice is harmless.
+    fn integrated(container: &Self::Container, id: Self::OpId) -> bool;
 }
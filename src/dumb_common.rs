@@ -1,9 +1,23 @@
-use std::{
-    collections::HashSet,
-    ops::{Deref, DerefMut},
+use std::{collections::HashSet, fmt::Debug, ops::Deref, rc::Rc};
+
+use crate::{
+    crdt::{GetOp, GetPos, OpSet},
+    id_span::IdSpanSet,
+    index_tree::VisibleIndex,
+    op_log::{LogOp, OpLog},
+    peer::PeerRegistry,
+    tie_break::mix,
 };
 
-use crate::crdt::{GetOp, OpSet};
+/// The reference containers use random 128-bit peer ids
+pub use crate::peer::PeerId;
+
+/// The random-looking id of the `index`-th peer in the tests.
+/// It's const, so it can name a peer in [crate::tie_break::Priority].
+pub const fn peer_id(index: usize) -> PeerId {
+    let index = index as u64;
+    ((mix(index) as u128) << 64) | mix(!index) as u128
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Op {
@@ -11,15 +25,29 @@ pub struct Op {
     pub lamport: u32,
     pub left: Option<OpId>,
     pub right: Option<OpId>,
+    /// only used by WOOTO and WOOTH
+    pub degree: usize,
     pub deleted: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct OpId {
-    pub client_id: usize,
+    pub client_id: PeerId,
     pub clock: usize,
 }
 
+impl LogOp for Op {
+    type Client = PeerId;
+
+    fn client(&self) -> PeerId {
+        self.id.client_id
+    }
+
+    fn clock(&self) -> usize {
+        self.id.clock
+    }
+}
+
 #[derive(Default)]
 pub struct OpSetImpl {
     pub set: HashSet<OpId>,
@@ -39,61 +67,195 @@ impl OpSet<Op, OpId> for OpSetImpl {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
-pub struct Content(Vec<Op>);
+/// The ops are shared between forks until one of them is modified. The first edit of a
+/// fork copies the whole vector and index, in O(n), later edits don't. The visible ops are
+/// indexed, so visible indices and ids are converted to positions in O(log n).
+#[derive(Clone, Default)]
+pub struct Content {
+    ops: Rc<Vec<Op>>,
+    index: Rc<VisibleIndex<OpId>>,
+}
 
 impl Deref for Content {
     type Target = Vec<Op>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.ops
+    }
+}
+
+impl PartialEq for Content {
+    fn eq(&self, other: &Self) -> bool {
+        self.ops == other.ops
+    }
+}
+
+impl Eq for Content {}
+
+impl Debug for Content {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.ops.fmt(f)
     }
 }
 
-impl DerefMut for Content {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+impl From<Vec<Op>> for Content {
+    fn from(ops: Vec<Op>) -> Self {
+        let mut index = VisibleIndex::default();
+        for (pos, op) in ops.iter().enumerate() {
+            index.insert(pos, op.id, !op.deleted);
+        }
+        Content {
+            ops: Rc::new(ops),
+            index: Rc::new(index),
+        }
     }
 }
 
 impl Content {
     pub fn real_len(&self) -> usize {
-        self.0.iter().filter(|x| !x.deleted).count()
+        self.index.visible_len()
     }
 
+    /// The position of the `index`-th visible op
     pub fn real_index(&self, index: usize) -> usize {
-        let mut real_index = 0;
-        for i in 0..self.0.len() {
-            if !self.0[i].deleted {
-                if real_index == index {
-                    return i;
-                }
-                real_index += 1;
+        self.index.visible_pos(index).expect("index out of range")
+    }
+
+    /// The position of the op `id`, tombstones included
+    pub fn pos_of(&self, id: OpId) -> Option<usize> {
+        self.index.pos_of(id)
+    }
+
+    /// The op `id`, tombstones included
+    pub fn get(&self, id: OpId) -> Option<&Op> {
+        self.pos_of(id).map(|pos| &self.ops[pos])
+    }
+
+    /// The id of the `index`-th visible op
+    pub fn visible_id(&self, index: usize) -> Option<OpId> {
+        self.index.visible_pos(index).map(|pos| self.ops[pos].id)
+    }
+
+    /// The id of the op after `id`, or of the first op if `id` is `None`
+    pub fn next_id(&self, id: Option<OpId>) -> Option<OpId> {
+        let pos = match id {
+            Some(id) => self.pos_of(id)? + 1,
+            None => 0,
+        };
+        self.ops.get(pos).map(|x| x.id)
+    }
+
+    pub fn insert(&mut self, pos: usize, op: Op) {
+        Rc::make_mut(&mut self.index).insert(pos, op.id, !op.deleted);
+        Rc::make_mut(&mut self.ops).insert(pos, op);
+    }
+
+    /// Mark the visible ops in `ids` as deleted
+    pub fn delete(&mut self, ids: &IdSpanSet<PeerId>) {
+        for (&client_id, clock) in ids.ids() {
+            let id = OpId { client_id, clock };
+            let Some(pos) = self.index.pos_of(id) else {
+                continue;
+            };
+            if self.ops[pos].deleted {
+                continue;
             }
+
+            Rc::make_mut(&mut self.index).set_visible(id, false);
+            Rc::make_mut(&mut self.ops)[pos].deleted = true;
         }
-        panic!("index out of range");
     }
 
     pub fn iter_real(&self) -> impl Iterator<Item = &Op> {
-        self.0.iter().filter(|x| !x.deleted)
+        self.ops.iter().filter(|x| !x.deleted)
     }
 
-    pub fn iter_real_mut(&mut self) -> impl Iterator<Item = &mut Op> {
-        self.0.iter_mut().filter(|x| !x.deleted)
+    /// The visible ops from the `index`-th one, nothing if it's out of range
+    pub fn iter_real_from(&self, index: usize) -> impl Iterator<Item = &Op> {
+        let start = self.index.visible_pos(index).unwrap_or(self.ops.len());
+        self.ops[start..].iter().filter(|x| !x.deleted)
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Container {
     pub content: Content,
-    /// exclusive end
+    /// the integrated ops, shared between forks like the content
+    pub log: Rc<OpLog<Op>>,
+    pub peers: PeerRegistry<PeerId>,
+    /// the ids of the tombstones
+    pub deleted: IdSpanSet<PeerId>,
+    /// exclusive end, indexed by [Container::peers]
     pub version_vector: Vec<usize>,
     pub max_clock: usize,
-    pub id: usize,
+    pub id: PeerId,
+}
+
+impl Container {
+    pub fn new(id: PeerId) -> Self {
+        Container {
+            id,
+            ..Default::default()
+        }
+    }
+
+    /// A branch with the same content, whose new ops are created by peer `id`
+    pub fn fork(&self, id: PeerId) -> Self {
+        Container {
+            content: self.content.clone(),
+            log: self.log.clone(),
+            peers: self.peers.clone(),
+            deleted: self.deleted.clone(),
+            version_vector: self.version_vector.clone(),
+            max_clock: 0,
+            id,
+        }
+    }
+
+    /// Record that `op` is integrated, the ops of every peer must be integrated in order.
+    /// Call it after the algorithm integrates `op`, which skips the ops in the log.
+    pub fn advance(&mut self, op: &Op) {
+        let id = op.id;
+        let index = self.peers.register(&id.client_id);
+        if self.version_vector.len() <= index {
+            self.version_vector.resize(index + 1, 0);
+        }
+
+        assert_eq!(self.version_vector[index], id.clock);
+        self.version_vector[index] = id.clock + 1;
+        Rc::make_mut(&mut self.log).push(op.clone());
+    }
+
+    /// The exclusive end of the integrated clocks of `peer`
+    pub fn end_of(&self, peer: &PeerId) -> usize {
+        self.peers
+            .index_of(peer)
+            .and_then(|index| self.version_vector.get(index).copied())
+            .unwrap_or(0)
+    }
+
+    /// The integrated ops that `other` misses, in causal order
+    pub fn ops_since<'a>(&'a self, other: &'a Container) -> impl Iterator<Item = &'a Op> {
+        self.log.iter_since(|peer| other.end_of(peer))
+    }
+
+    /// Whether all the ops in `ids` are integrated
+    pub fn contains_all(&self, ids: &IdSpanSet<PeerId>) -> bool {
+        // the clocks of a peer are integrated in order
+        ids.iter().all(|(peer, span)| span.end <= self.end_of(peer))
+    }
+
+    /// Whether the op `id` is integrated, `None` is the start of the list
+    pub fn contains(&self, id: Option<OpId>) -> bool {
+        match id {
+            Some(id) => self.log.contains(&id.client_id, id.clock),
+            None => true,
+        }
+    }
 }
 
 pub struct Iter<'a> {
-    pub arr: &'a mut Vec<Op>,
+    pub arr: &'a [Op],
     pub index: usize,
     pub start: Option<OpId>,
     pub end: Option<OpId>,
@@ -103,7 +265,7 @@ pub struct Iter<'a> {
 }
 
 pub struct Cursor<'a> {
-    pub arr: &'a mut Vec<Op>,
+    pub arr: &'a [Op],
     pub pos: usize,
 }
 
@@ -149,7 +311,7 @@ impl<'a> Iterator for Iter<'a> {
         }
 
         Some(Cursor {
-            arr: unsafe { &mut *(self.arr as *mut _) },
+            arr: self.arr,
             pos: self.index - 1,
         })
     }
@@ -158,7 +320,15 @@ impl<'a> Iterator for Iter<'a> {
 impl<'a> GetOp for Cursor<'a> {
     type Target = Op;
 
-    fn get_op(&self) -> Self::Target {
-        self.arr[self.pos].clone()
+    fn get_op_ref(&self) -> &Self::Target {
+        &self.arr[self.pos]
+    }
+}
+
+impl<'a> GetPos for Cursor<'a> {
+    type Pos = usize;
+
+    fn get_pos(&self) -> Self::Pos {
+        self.pos
     }
 }
@@ -1,9 +1,9 @@
 //! This mod impl The Art of the Fugue: Minimizing Interleaving in Collaborative Text Editing
 //!
 
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::HashMap, hash::Hash};
 
-use crate::crdt::{GetOp, ListCrdt, OpSet};
+use crate::crdt::{GetOp, GetPos, ListCrdt, OpSet};
 
 /// For Fugue, iter should only iterate over the element between `start` and `to`, exclude both `start` and `to`
 pub trait Fugue: ListCrdt {
@@ -12,7 +12,12 @@ pub trait Fugue: ListCrdt {
     fn left_origin_of_id(container: &Self::Container, op_id: &Self::OpId) -> Option<Self::OpId>;
     fn right_origin(op: &Self::OpUnit) -> Option<Self::OpId>;
     /// insert after the anchor
-    fn insert_after(anchor: Self::Cursor<'_>, op: Self::OpUnit, context: &mut Self::Context);
+    fn insert_after(
+        container: &mut Self::Container,
+        anchor: Self::Pos,
+        op: Self::OpUnit,
+        context: &mut Self::Context,
+    );
     fn insert_after_id(
         container: &mut Self::Container,
         id: Option<Self::OpId>,
@@ -31,6 +36,10 @@ pub fn integrate<T: Fugue>(
     to_insert: T::OpUnit,
     ctx: &mut T::Context,
 ) {
+    if T::integrated(container, T::id(&to_insert)) {
+        return;
+    }
+
     let this_left_origin = T::left_origin(&to_insert);
     let this_right_origin = T::right_origin(&to_insert);
     let this_right_parent = this_right_origin.and_then(|x| {
@@ -44,19 +53,15 @@ pub fn integrate<T: Fugue>(
     let mut visited = T::Set::default();
     let mut scanning = false;
 
-    for other_cursor in T::iter(
-        unsafe { std::mem::transmute(&mut *container) },
-        this_left_origin,
-        this_right_origin,
-    ) {
-        let other = other_cursor.get_op();
-        if (this_left_origin.is_some() && T::contains(&other, this_left_origin.unwrap()))
-            || (this_right_origin.is_some() && T::contains(&other, this_right_origin.unwrap()))
+    for other_cursor in T::iter(container, this_left_origin, this_right_origin) {
+        let other = other_cursor.get_op_ref();
+        if (this_left_origin.is_some() && T::contains(other, this_left_origin.unwrap()))
+            || (this_right_origin.is_some() && T::contains(other, this_right_origin.unwrap()))
         {
             unreachable!("For Fugue iter should only iterate over the element between `start` and `to`, exclude both `start` and `to`");
         }
 
-        let o_left_origin = T::left_origin(&other);
+        let o_left_origin = T::left_origin(other);
 
         // o.leftOrigin < elt.leftOrigin (< compares the position)
         if o_left_origin.map(|x| !visited.contain(x)).unwrap_or(true)
@@ -65,9 +70,9 @@ pub fn integrate<T: Fugue>(
             break;
         }
 
-        visited.insert(&other);
+        visited.insert(other);
         if o_left_origin == this_left_origin {
-            let o_right_parent = T::right_origin(&other).and_then(|x| {
+            let o_right_parent = T::right_origin(other).and_then(|x| {
                 if T::left_origin_of_id(container, &x) == this_left_origin {
                     Some(x)
                 } else {
@@ -79,7 +84,7 @@ pub fn integrate<T: Fugue>(
                 Ordering::Less => {
                     scanning = true;
                 }
-                Ordering::Equal if T::cmp_id(&other, &to_insert) == Ordering::Greater => {
+                Ordering::Equal if T::cmp_id(other, &to_insert) == Ordering::Greater => {
                     break;
                 }
                 _ => {
@@ -89,15 +94,85 @@ pub fn integrate<T: Fugue>(
         }
 
         if !scanning {
-            cursor = Some(other_cursor);
+            cursor = Some(other_cursor.get_pos());
         }
     }
 
     if let Some(cursor) = cursor {
-        T::insert_after(cursor, to_insert, ctx);
+        T::insert_after(container, cursor, to_insert, ctx);
         return;
     }
 
-    drop(cursor);
     T::insert_after_id(container, this_left_origin, to_insert, ctx);
 }
+
+/// Order `ops` in one pass, without integrating them one by one, e.g. to merge two replicas.
+/// The result is the same as integrating the ops in any causal order.
+/// `ops` may contain the same op more than once, but it must contain the origins of every op.
+///
+/// Every op is a left child of its right origin if they have the same left origin,
+/// otherwise a right child of its left origin. The list is the in-order traversal of
+/// that tree, where the siblings are ordered by [ListCrdt::cmp_id].
+pub fn merge<T: Fugue>(ops: impl IntoIterator<Item = T::OpUnit>) -> Vec<T::OpUnit>
+where
+    T::OpId: Hash,
+{
+    let mut nodes: Vec<T::OpUnit> = Vec::new();
+    let mut index_of: HashMap<T::OpId, usize> = HashMap::new();
+    for op in ops {
+        index_of.entry(T::id(&op)).or_insert_with(|| {
+            nodes.push(op);
+            nodes.len() - 1
+        });
+    }
+
+    // the last slot is the start of the list
+    let root = nodes.len();
+    let mut left_children: Vec<Vec<usize>> = vec![Vec::new(); nodes.len() + 1];
+    let mut right_children: Vec<Vec<usize>> = vec![Vec::new(); nodes.len() + 1];
+    for (i, op) in nodes.iter().enumerate() {
+        let left = T::left_origin(op);
+        let right_parent = T::right_origin(op)
+            .map(|x| index_of[&x])
+            .filter(|&x| T::left_origin(&nodes[x]) == left);
+        match right_parent {
+            Some(parent) => left_children[parent].push(i),
+            None => right_children[left.map(|x| index_of[&x]).unwrap_or(root)].push(i),
+        }
+    }
+
+    for children in left_children.iter_mut().chain(right_children.iter_mut()) {
+        children.sort_by(|&a, &b| T::cmp_id(&nodes[a], &nodes[b]));
+    }
+
+    // iterative in-order traversal, so deep trees don't overflow the stack
+    enum Visit {
+        Enter(usize),
+        Emit(usize),
+    }
+
+    let mut order = Vec::with_capacity(nodes.len());
+    let mut stack = vec![Visit::Enter(root)];
+    while let Some(visit) = stack.pop() {
+        match visit {
+            Visit::Enter(node) => {
+                for &child in right_children[node].iter().rev() {
+                    stack.push(Visit::Enter(child));
+                }
+                if node != root {
+                    stack.push(Visit::Emit(node));
+                }
+                for &child in left_children[node].iter().rev() {
+                    stack.push(Visit::Enter(child));
+                }
+            }
+            Visit::Emit(node) => order.push(node),
+        }
+    }
+
+    let mut nodes: Vec<Option<T::OpUnit>> = nodes.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .map(|i| nodes[i].take().unwrap())
+        .collect()
+}
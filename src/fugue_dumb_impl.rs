@@ -1,35 +1,48 @@
-use std::{cmp::Ordering, collections::HashSet};
+use std::cmp::Ordering;
+use std::marker::PhantomData;
 
-pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl};
-use crate::{crdt::ListCrdt, fugue, test::TestFramework};
+use crate::dumb_common::peer_id;
+pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl, PeerId};
+use crate::id_span::IdSpanSet;
+use crate::tie_break::{Ascending, TieBreak};
+use crate::{crdt::ListCrdt, fugue, local::Tombstones, test::TestFramework};
 
-impl FugueImpl {
+impl<P: TieBreak<PeerId>> FugueImpl<P> {
     fn container_contains(
         container: &<Self as ListCrdt>::Container,
         op_id: Option<<Self as ListCrdt>::OpId>,
     ) -> bool {
-        if op_id.is_none() {
-            return true;
-        }
-        let op_id = op_id.unwrap();
-        container.content.iter().any(|x| x.id == op_id)
+        container.contains(op_id)
+    }
 
-        // if container.version_vector.len() <= op_id.client_id {
-        //     return false;
-        // }
+    /// Merge `b` into a copy of `a` without integrating the ops one by one
+    pub fn bulk_merge(a: &Container, b: &Container) -> Container {
+        let deleted = a.deleted.union(&b.deleted);
+        let mut content = fugue::merge::<Self>(a.content.iter().chain(b.content.iter()).cloned());
+        for op in content.iter_mut() {
+            op.deleted = deleted.contains(&op.id.client_id, op.id.clock);
+        }
 
-        // container.version_vector[op_id.client_id] > op_id.clock
+        let mut merged = a.clone();
+        for op in b.ops_since(a) {
+            merged.advance(op);
+        }
+        merged.content = content.into();
+        merged.deleted = deleted;
+        merged
     }
 }
 
-pub struct FugueImpl;
-impl ListCrdt for FugueImpl {
+pub struct FugueImpl<P = Ascending>(PhantomData<P>);
+impl<P: TieBreak<PeerId>> ListCrdt for FugueImpl<P> {
     type OpUnit = Op;
 
     type OpId = OpId;
 
     type Container = Container;
 
+    type Pos = usize;
+
     type Cursor<'a> = Cursor<'a>;
 
     type Set = OpSetImpl;
@@ -37,12 +50,12 @@ impl ListCrdt for FugueImpl {
     type Iterator<'a> = Iter<'a>;
 
     fn iter(
-        container: &mut Self::Container,
+        container: &Self::Container,
         from: Option<Self::OpId>,
         to: Option<Self::OpId>,
     ) -> Self::Iterator<'_> {
         Iter {
-            arr: &mut container.content,
+            arr: &container.content,
             index: 0,
             start: from,
             end: to,
@@ -57,18 +70,34 @@ impl ListCrdt for FugueImpl {
     }
 
     fn cmp_id(op_a: &Self::OpUnit, op_b: &Self::OpUnit) -> std::cmp::Ordering {
-        op_a.id
-            .client_id
-            .cmp(&op_b.id.client_id)
+        P::cmp_client(&op_a.id.client_id, &op_b.id.client_id)
             .then(op_a.id.clock.cmp(&op_b.id.clock))
     }
 
     fn contains(op: &Self::OpUnit, id: Self::OpId) -> bool {
         op.id == id
     }
+
+    fn integrated(container: &Self::Container, id: Self::OpId) -> bool {
+        container.contains(Some(id))
+    }
 }
 
-impl fugue::Fugue for FugueImpl {
+impl<P: TieBreak<PeerId>> Tombstones for FugueImpl<P> {
+    fn get(container: &Self::Container, id: OpId) -> Option<&Op> {
+        container.content.get(id)
+    }
+
+    fn visible_id(container: &Self::Container, index: usize) -> Option<OpId> {
+        container.content.visible_id(index)
+    }
+
+    fn next_id(container: &Self::Container, id: Option<OpId>) -> Option<OpId> {
+        container.content.next_id(id)
+    }
+}
+
+impl<P: TieBreak<PeerId>> fugue::Fugue for FugueImpl<P> {
     type Context = ();
     fn left_origin(op: &Self::OpUnit) -> Option<Self::OpId> {
         op.left
@@ -78,12 +107,8 @@ impl fugue::Fugue for FugueImpl {
         op.right
     }
 
-    fn insert_after(anchor: Self::Cursor<'_>, op: Self::OpUnit, _: &mut ()) {
-        if anchor.pos + 1 >= anchor.arr.len() {
-            anchor.arr.push(op);
-        } else {
-            anchor.arr.insert(anchor.pos + 1, op);
-        }
+    fn insert_after(container: &mut Self::Container, anchor: usize, op: Self::OpUnit, _: &mut ()) {
+        container.content.insert(anchor + 1, op);
     }
 
     fn insert_after_id(
@@ -93,7 +118,7 @@ impl fugue::Fugue for FugueImpl {
         _: &mut (),
     ) {
         if let Some(id) = id {
-            let pos = container.content.iter().position(|x| x.id == id).unwrap();
+            let pos = container.content.pos_of(id).unwrap();
             container.content.insert(pos + 1, op);
         } else {
             container.content.insert(0, op);
@@ -101,13 +126,11 @@ impl fugue::Fugue for FugueImpl {
     }
 
     fn left_origin_of_id(container: &Self::Container, op_id: &Self::OpId) -> Option<Self::OpId> {
-        for op in container.content.iter() {
-            if op.id == *op_id {
-                return op.left;
-            }
-        }
-
-        panic!("Cannot find left origin")
+        container
+            .log
+            .get(&op_id.client_id, op_id.clock)
+            .expect("Cannot find left origin")
+            .left
     }
 
     fn cmp_pos(
@@ -120,26 +143,14 @@ impl fugue::Fugue for FugueImpl {
             (None, Some(_)) => Ordering::Greater,
             (Some(_), None) => Ordering::Less,
             (Some(a), Some(b)) => {
-                if a == b {
-                    return Ordering::Equal;
-                }
-
-                for op in container.content.iter() {
-                    if op.id == a {
-                        return Ordering::Less;
-                    }
-                    if op.id == b {
-                        return Ordering::Greater;
-                    }
-                }
-
-                panic!("not found")
+                let pos = |id| container.content.pos_of(id).expect("not found");
+                pos(a).cmp(&pos(b))
             }
         }
     }
 }
 
-impl TestFramework for FugueImpl {
+impl<P: TieBreak<PeerId>> TestFramework for FugueImpl<P> {
     fn is_content_eq(a: &Self::Container, b: &Self::Container) -> bool {
         match a.content.eq(&b.content) {
             true => true,
@@ -147,12 +158,12 @@ impl TestFramework for FugueImpl {
         }
     }
 
+    fn fork(container: &Self::Container, id: usize) -> Self::Container {
+        container.fork(peer_id(id))
+    }
+
     fn new_container(id: usize) -> Self::Container {
-        Container {
-            id,
-            version_vector: vec![0; 10],
-            ..Default::default()
-        }
+        Container::new(peer_id(id))
     }
 
     fn new_op(container: &mut Self::Container, pos: usize) -> Self::OpUnit {
@@ -177,6 +188,7 @@ impl TestFramework for FugueImpl {
             },
             left,
             right,
+            degree: 0,
             deleted: false,
             lamport: 0,
         };
@@ -185,34 +197,33 @@ impl TestFramework for FugueImpl {
         ans
     }
 
-    type DeleteOp = HashSet<Self::OpId>;
+    type DeleteOp = IdSpanSet<PeerId>;
 
     fn new_del_op(container: &Self::Container, pos: usize, len: usize) -> Self::DeleteOp {
-        let mut deleted = HashSet::new();
-        for op in container.content.iter_real().skip(pos).take(len) {
-            deleted.insert(op.id);
+        let mut deleted = IdSpanSet::new();
+        for op in container.content.iter_real_from(pos).take(len) {
+            deleted.insert(op.id.client_id, op.id.clock);
         }
 
         deleted
     }
 
     fn integrate_delete_op(container: &mut Self::Container, delete_set: Self::DeleteOp) {
-        for op in container.content.iter_real_mut() {
-            if delete_set.contains(&op.id) {
-                op.deleted = true;
-            }
-        }
+        container.content.delete(&delete_set);
+        container.deleted = container.deleted.union(&delete_set);
+    }
+
+    fn can_integrate_delete_op(container: &Self::Container, op: &Self::DeleteOp) -> bool {
+        container.contains_all(op)
     }
 
     fn integrate(container: &mut Self::Container, op: Self::OpUnit) {
-        let id = Self::id(&op);
-        for _ in container.version_vector.len()..id.client_id + 1 {
-            container.version_vector.push(0);
+        if container.contains(Some(op.id)) {
+            return;
         }
-        assert!(container.version_vector[id.client_id] == id.clock);
-        fugue::integrate::<FugueImpl>(container, op, &mut ());
 
-        container.version_vector[id.client_id] = id.clock + 1;
+        fugue::integrate::<Self>(container, op.clone(), &mut ());
+        container.advance(&op);
     }
 
     fn can_integrate(container: &Self::Container, op: &Self::OpUnit) -> bool {
@@ -281,6 +292,104 @@ mod fugue_impl_test {
         }
     }
 
+    #[test]
+    fn fork_merge() {
+        for seed in 0..100 {
+            crate::test::test_fork_merge::<FugueImpl>(seed, 3, 50);
+        }
+    }
+
+    #[test]
+    fn bulk_merge() {
+        for seed in 0..100 {
+            crate::test::test_bulk_merge::<FugueImpl>(seed, 3, 200, <FugueImpl>::bulk_merge);
+            crate::test::test_bulk_merge::<FugueImpl<crate::tie_break::Hashed>>(
+                seed,
+                4,
+                200,
+                FugueImpl::<crate::tie_break::Hashed>::bulk_merge,
+            );
+        }
+    }
+
+    #[test]
+    fn exhaustive() {
+        crate::test::explore::<FugueImpl>(2, 4, 2);
+        crate::test::explore::<FugueImpl>(3, 3, 2);
+    }
+
+    #[test]
+    fn delivery_orders() {
+        for seed in 0..20 {
+            crate::test::test_delivery_orders::<FugueImpl>(seed, 3, 200, 10);
+        }
+    }
+
+    #[test]
+    fn lossy_network() {
+        for seed in 0..10 {
+            crate::network::test_network::<FugueImpl>(seed, 4, 200, Default::default());
+        }
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        for seed in 0..20 {
+            crate::test::test_duplicates::<FugueImpl>(seed, 3, 300);
+        }
+    }
+
+    #[test]
+    fn tie_break_policies() {
+        use crate::{
+            dumb_common::peer_id,
+            test::tie,
+            tie_break::{Ascending, Descending, Hashed, Priority},
+        };
+        type Server = Priority<{ peer_id(1) }>;
+        // peer 0 has the greater id, but peer 1 wins the tie
+        assert_eq!(
+            tie::<FugueImpl<Server>>(0, 1),
+            tie::<FugueImpl<Descending>>(0, 1)
+        );
+        assert_ne!(
+            tie::<FugueImpl<Server>>(0, 1),
+            tie::<FugueImpl<Ascending>>(0, 1)
+        );
+        for seed in 0..30 {
+            crate::test::test::<FugueImpl<Descending>>(seed, 3, 300);
+            crate::test::test::<FugueImpl<Server>>(seed, 3, 300);
+            crate::test::test::<FugueImpl<Hashed>>(seed, 5, 300);
+            crate::test::test_fork_merge::<FugueImpl<Hashed>>(seed, 3, 50);
+        }
+    }
+
+    #[test]
+    fn sync_with_op_log() {
+        let mut a = <FugueImpl>::new_container(0);
+        let mut b = <FugueImpl>::new_container(1);
+        for i in 0..20 {
+            let container = if i % 3 == 0 { &mut b } else { &mut a };
+            let op = <FugueImpl>::new_op(container, i * 7);
+            <FugueImpl>::integrate(container, op);
+        }
+
+        let missing: Vec<Op> = a.ops_since(&b).cloned().collect();
+        assert_eq!(missing.len(), 13);
+        for op in missing {
+            assert!(<FugueImpl>::can_integrate(&b, &op));
+            <FugueImpl>::integrate(&mut b, op);
+        }
+        let missing: Vec<Op> = b.ops_since(&a).cloned().collect();
+        for op in missing {
+            <FugueImpl>::integrate(&mut a, op);
+        }
+
+        assert_eq!(a.content, b.content);
+        assert_eq!(a.ops_since(&b).count(), 0);
+        assert_eq!(a.log.len(), 20);
+    }
+
     use ctor::ctor;
     #[ctor]
     fn init_color_backtrace() {
@@ -0,0 +1,250 @@
+//! Sets of op ids stored as clock ranges per client.
+//!
+//! A client creates its ops with consecutive clocks, and users delete ranges of text,
+//! so the ids of a delete op are mostly a few spans. The spans of a client are kept sorted
+//! and merged when they overlap or touch, so equal sets have equal spans.
+
+use std::{collections::BTreeMap, fmt::Debug, ops::Range};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdSpanSet<C> {
+    /// the disjoint spans of every client, the end of a span by its start
+    spans: BTreeMap<C, BTreeMap<usize, usize>>,
+}
+
+impl<C> Default for IdSpanSet<C> {
+    fn default() -> Self {
+        IdSpanSet {
+            spans: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C: Ord + Clone> IdSpanSet<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// The number of ids, saturating at `usize::MAX`
+    pub fn len(&self) -> usize {
+        self.iter()
+            .fold(0, |len: usize, (_, span)| len.saturating_add(span.len()))
+    }
+
+    /// Insert the id `clock` of `client`. Spans end at `usize::MAX` at most,
+    /// so the clock `usize::MAX` can't be stored and is ignored
+    pub fn insert(&mut self, client: C, clock: usize) {
+        self.insert_span(client, clock..clock.saturating_add(1));
+    }
+
+    /// Insert the clocks `span` of `client`, merging it with the spans it overlaps or touches
+    pub fn insert_span(&mut self, client: C, span: Range<usize>) {
+        if span.is_empty() {
+            return;
+        }
+
+        let spans = self.spans.entry(client).or_default();
+        let (mut start, mut end) = (span.start, span.end);
+        let touched: Vec<(usize, usize)> = spans
+            .range(..=end)
+            .rev()
+            .take_while(|(_, &e)| e >= start)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (s, e) in touched {
+            spans.remove(&s);
+            start = std::cmp::min(start, s);
+            end = std::cmp::max(end, e);
+        }
+        spans.insert(start, end);
+    }
+
+    pub fn contains(&self, client: &C, clock: usize) -> bool {
+        self.spans
+            .get(client)
+            .and_then(|spans| spans.range(..=clock).next_back())
+            .map(|(_, &end)| clock < end)
+            .unwrap_or(false)
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut ans = self.clone();
+        ans.extend(other);
+        ans
+    }
+
+    /// Insert the spans of `other` in place, in O(spans of `other` * log(spans of `self`))
+    pub fn extend(&mut self, other: &Self) {
+        for (client, span) in other.iter() {
+            self.insert_span(client.clone(), span);
+        }
+    }
+
+    /// The ids of `self` that `other` doesn't contain
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut ans = Self::new();
+        for (client, span) in self.iter() {
+            let mut start = span.start;
+            if let Some(removed) = other.spans.get(client) {
+                let mut overlaps: Vec<(usize, usize)> = removed
+                    .range(..span.end)
+                    .rev()
+                    .take_while(|(_, &e)| e > span.start)
+                    .map(|(&s, &e)| (s, e))
+                    .collect();
+                overlaps.reverse();
+                for (s, e) in overlaps {
+                    ans.insert_span(client.clone(), start..std::cmp::max(start, s));
+                    start = std::cmp::max(start, e);
+                }
+            }
+            ans.insert_span(client.clone(), start..std::cmp::max(start, span.end));
+        }
+        ans
+    }
+
+    /// The spans, ordered by client and then by clock
+    pub fn iter(&self) -> impl Iterator<Item = (&C, Range<usize>)> {
+        self.spans
+            .iter()
+            .flat_map(|(client, spans)| spans.iter().map(move |(&s, &e)| (client, s..e)))
+    }
+
+    /// All the ids, ordered like [IdSpanSet::iter]
+    pub fn ids(&self) -> impl Iterator<Item = (&C, usize)> {
+        self.iter()
+            .flat_map(|(client, span)| span.map(move |clock| (client, clock)))
+    }
+
+    /// The spans of every client as `(gap from the end of the previous span, len)` pairs,
+    /// which are small numbers for the wire
+    pub fn encode(&self) -> Vec<(C, Vec<(usize, usize)>)> {
+        self.spans
+            .iter()
+            .map(|(client, spans)| {
+                let mut last_end = 0;
+                let spans = spans
+                    .iter()
+                    .map(|(&s, &e)| {
+                        let pair = (s - last_end, e - s);
+                        last_end = e;
+                        pair
+                    })
+                    .collect();
+                (client.clone(), spans)
+            })
+            .collect()
+    }
+
+    /// The inverse of [IdSpanSet::encode], `None` if a span ends after `usize::MAX`
+    pub fn decode(encoded: &[(C, Vec<(usize, usize)>)]) -> Option<Self> {
+        let mut ans = Self::new();
+        for (client, spans) in encoded {
+            let mut last_end: usize = 0;
+            for &(gap, len) in spans {
+                let start = last_end.checked_add(gap)?;
+                let end = start.checked_add(len)?;
+                ans.insert_span(client.clone(), start..end);
+                last_end = end;
+            }
+        }
+        Some(ans)
+    }
+}
+
+impl<C: Ord + Clone> FromIterator<(C, usize)> for IdSpanSet<C> {
+    fn from_iter<I: IntoIterator<Item = (C, usize)>>(iter: I) -> Self {
+        let mut ans = Self::new();
+        for (client, clock) in iter {
+            ans.insert(client, clock);
+        }
+        ans
+    }
+}
+
+#[cfg(test)]
+mod id_span_test {
+    use super::*;
+
+    fn spans(set: &IdSpanSet<char>) -> Vec<(char, Range<usize>)> {
+        set.iter().map(|(c, span)| (*c, span)).collect()
+    }
+
+    #[test]
+    fn spans_are_normalized() {
+        let mut set = IdSpanSet::new();
+        set.insert_span('a', 5..8);
+        set.insert_span('a', 0..2);
+        set.insert('a', 2);
+        set.insert_span('a', 4..5);
+        set.insert_span('b', 3..3);
+        assert_eq!(spans(&set), vec![('a', 0..3), ('a', 4..8)]);
+        set.insert('a', 3);
+        assert_eq!(spans(&set), vec![('a', 0..8)]);
+        assert_eq!(set.len(), 8);
+        assert!(set.contains(&'a', 7));
+        assert!(!set.contains(&'a', 8));
+        assert!(!set.contains(&'b', 3));
+
+        let collected: IdSpanSet<char> = [('b', 1), ('a', 2), ('b', 0)].into_iter().collect();
+        assert_eq!(spans(&collected), vec![('a', 2..3), ('b', 0..2)]);
+    }
+
+    #[test]
+    fn boundary() {
+        let mut set = IdSpanSet::new();
+        set.insert('a', usize::MAX - 1);
+        set.insert('a', usize::MAX);
+        assert_eq!(spans(&set), vec![('a', usize::MAX - 1..usize::MAX)]);
+        assert!(set.contains(&'a', usize::MAX - 1));
+        assert!(!set.contains(&'a', usize::MAX));
+
+        set.insert_span('a', 0..usize::MAX);
+        set.insert_span('b', 0..usize::MAX);
+        assert_eq!(set.len(), usize::MAX);
+    }
+
+    #[test]
+    fn union_and_difference() {
+        let mut a = IdSpanSet::new();
+        a.insert_span('a', 0..10);
+        a.insert_span('b', 0..4);
+        let mut b = IdSpanSet::new();
+        b.insert_span('a', 2..4);
+        b.insert_span('a', 6..12);
+        b.insert_span('c', 0..1);
+
+        assert_eq!(
+            spans(&a.difference(&b)),
+            vec![('a', 0..2), ('a', 4..6), ('b', 0..4)]
+        );
+        assert_eq!(spans(&b.difference(&a)), vec![('a', 10..12), ('c', 0..1)]);
+        assert_eq!(
+            spans(&a.union(&b)),
+            vec![('a', 0..12), ('b', 0..4), ('c', 0..1)]
+        );
+        assert!(a.difference(&a).is_empty());
+    }
+
+    #[test]
+    fn encode() {
+        let mut set = IdSpanSet::new();
+        set.insert_span('a', 3..5);
+        set.insert_span('a', 9..10);
+        set.insert_span('b', 0..100);
+        let encoded = set.encode();
+        assert_eq!(
+            encoded,
+            vec![('a', vec![(3, 2), (4, 1)]), ('b', vec![(0, 100)])]
+        );
+        assert_eq!(IdSpanSet::decode(&encoded), Some(set));
+        assert_eq!(
+            IdSpanSet::<char>::decode(&[('a', vec![(5, 1), (usize::MAX, 1)])]),
+            None
+        );
+    }
+}
@@ -0,0 +1,432 @@
+//! An implicit treap over a sequence of values.
+//!
+//! Every node has a metric and every subtree caches the sum of its metrics, so
+//! converting a position between the index and the different metrics takes O(log n).
+//! Nodes keep their [NodeId] until they are removed, and [IndexTree::index_of] finds
+//! the index of a node by walking up the parents.
+//!
+//! [VisibleIndex] uses it to convert between the positions of the ops in a container,
+//! tombstones included, the visible indices and the op ids.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    ops::{Add, Sub},
+};
+
+pub(crate) trait Metric: Copy + Default + Add<Output = Self> + Sub<Output = Self> {}
+
+impl<T: Copy + Default + Add<Output = T> + Sub<Output = T>> Metric for T {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+struct Node<T, M> {
+    /// `None` if the node is removed and its slot is waiting to be reused
+    value: Option<T>,
+    metric: M,
+    sum: M,
+    size: usize,
+    priority: u32,
+    parent: Option<usize>,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct IndexTree<T, M> {
+    nodes: Vec<Node<T, M>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    seed: u32,
+}
+
+impl<T, M: Metric> Default for IndexTree<T, M> {
+    fn default() -> Self {
+        IndexTree {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+            seed: 0x9e37_79b9,
+        }
+    }
+}
+
+impl<T, M: Metric> IndexTree<T, M> {
+    pub fn len(&self) -> usize {
+        self.size(self.root)
+    }
+
+    pub fn total(&self) -> M {
+        self.sum(self.root)
+    }
+
+    pub fn get(&self, node: NodeId) -> &T {
+        self.nodes[node.0].value.as_ref().unwrap()
+    }
+
+    /// Change the value of `node`, without changing its metric
+    pub fn get_mut(&mut self, node: NodeId) -> &mut T {
+        self.nodes[node.0].value.as_mut().unwrap()
+    }
+
+    pub fn metric(&self, node: NodeId) -> M {
+        self.nodes[node.0].metric
+    }
+
+    /// Change the metric of `node`, the sums of its ancestors are updated on the way up
+    pub fn set_metric(&mut self, node: NodeId, metric: M) {
+        self.nodes[node.0].metric = metric;
+        let mut current = Some(node.0);
+        while let Some(n) = current {
+            self.update(n);
+            current = self.nodes[n].parent;
+        }
+    }
+
+    /// Insert `value` so that it becomes the `index`-th node
+    pub fn insert(&mut self, index: usize, value: T, metric: M) -> NodeId {
+        assert!(index <= self.len(), "index out of range");
+        let node = Node {
+            value: Some(value),
+            metric,
+            sum: metric,
+            size: 1,
+            priority: self.next_priority(),
+            parent: None,
+            left: None,
+            right: None,
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        let (a, b) = self.split(self.root, index);
+        let a = self.merge(a, Some(id));
+        let root = self.merge(a, b);
+        self.set_root(root);
+        NodeId(id)
+    }
+
+    pub fn remove(&mut self, node: NodeId) -> T {
+        let index = self.index_of(node);
+        let (a, b) = self.split(self.root, index);
+        let (removed, b) = self.split(b, 1);
+        debug_assert_eq!(removed, Some(node.0));
+        let root = self.merge(a, b);
+        self.set_root(root);
+        self.free.push(node.0);
+        self.nodes[node.0].value.take().unwrap()
+    }
+
+    pub fn index_of(&self, node: NodeId) -> usize {
+        let mut n = node.0;
+        let mut index = self.size(self.nodes[n].left);
+        while let Some(parent) = self.nodes[n].parent {
+            if self.nodes[parent].right == Some(n) {
+                index += self.size(self.nodes[parent].left) + 1;
+            }
+            n = parent;
+        }
+
+        index
+    }
+
+    pub fn node_at(&self, mut index: usize) -> Option<NodeId> {
+        let mut current = self.root;
+        while let Some(n) = current {
+            let left_size = self.size(self.nodes[n].left);
+            match index.cmp(&left_size) {
+                std::cmp::Ordering::Less => current = self.nodes[n].left,
+                std::cmp::Ordering::Equal => return Some(NodeId(n)),
+                std::cmp::Ordering::Greater => {
+                    index -= left_size + 1;
+                    current = self.nodes[n].right;
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The sum of the metrics of the first `index` nodes
+    pub fn prefix(&self, mut index: usize) -> M {
+        let mut ans = M::default();
+        let mut current = self.root;
+        while let Some(n) = current {
+            let left = self.nodes[n].left;
+            let left_size = self.size(left);
+            if index <= left_size {
+                if index == left_size {
+                    return ans + self.sum(left);
+                }
+
+                current = left;
+            } else {
+                ans = ans + self.sum(left) + self.nodes[n].metric;
+                index -= left_size + 1;
+                current = self.nodes[n].right;
+            }
+        }
+
+        ans
+    }
+
+    /// Find the node that contains `offset`, measured by `key`.
+    /// It returns the index of the node and the remaining offset inside it.
+    /// If `offset` is not less than the total, the index is [IndexTree::len].
+    pub fn find(&self, mut offset: usize, key: impl Fn(&M) -> usize) -> (usize, usize) {
+        let mut index = 0;
+        let mut current = self.root;
+        while let Some(n) = current {
+            let left = self.nodes[n].left;
+            let left_len = key(&self.sum(left));
+            if offset < left_len {
+                current = left;
+                continue;
+            }
+
+            offset -= left_len;
+            index += self.size(left);
+            let len = key(&self.nodes[n].metric);
+            if offset < len {
+                return (index, offset);
+            }
+
+            offset -= len;
+            index += 1;
+            current = self.nodes[n].right;
+        }
+
+        (index, offset)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        let mut stack = Vec::new();
+        let mut current = self.root;
+        std::iter::from_fn(move || {
+            while let Some(n) = current {
+                stack.push(n);
+                current = self.nodes[n].left;
+            }
+
+            let n = stack.pop()?;
+            current = self.nodes[n].right;
+            self.nodes[n].value.as_ref()
+        })
+    }
+
+    fn size(&self, node: Option<usize>) -> usize {
+        node.map(|x| self.nodes[x].size).unwrap_or(0)
+    }
+
+    fn sum(&self, node: Option<usize>) -> M {
+        node.map(|x| self.nodes[x].sum).unwrap_or_default()
+    }
+
+    fn next_priority(&mut self) -> u32 {
+        // xorshift32
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    fn set_root(&mut self, root: Option<usize>) {
+        self.root = root;
+        if let Some(root) = root {
+            self.nodes[root].parent = None;
+        }
+    }
+
+    fn update(&mut self, n: usize) {
+        let Node {
+            left,
+            right,
+            metric,
+            ..
+        } = self.nodes[n];
+        self.nodes[n].size = self.size(left) + self.size(right) + 1;
+        self.nodes[n].sum = self.sum(left) + metric + self.sum(right);
+        for child in [left, right].into_iter().flatten() {
+            self.nodes[child].parent = Some(n);
+        }
+    }
+
+    /// Split into the first `k` nodes and the rest
+    fn split(&mut self, node: Option<usize>, k: usize) -> (Option<usize>, Option<usize>) {
+        let Some(n) = node else {
+            return (None, None);
+        };
+
+        let left_size = self.size(self.nodes[n].left);
+        if k <= left_size {
+            let (a, b) = self.split(self.nodes[n].left, k);
+            self.nodes[n].left = b;
+            self.update(n);
+            (a, Some(n))
+        } else {
+            let (a, b) = self.split(self.nodes[n].right, k - left_size - 1);
+            self.nodes[n].right = a;
+            self.update(n);
+            (Some(n), b)
+        }
+    }
+
+    fn merge(&mut self, a: Option<usize>, b: Option<usize>) -> Option<usize> {
+        match (a, b) {
+            (None, x) | (x, None) => x,
+            (Some(a), Some(b)) => {
+                if self.nodes[a].priority > self.nodes[b].priority {
+                    let right = self.merge(self.nodes[a].right, Some(b));
+                    self.nodes[a].right = right;
+                    self.update(a);
+                    Some(a)
+                } else {
+                    let left = self.merge(Some(a), self.nodes[b].left);
+                    self.nodes[b].left = left;
+                    self.update(b);
+                    Some(b)
+                }
+            }
+        }
+    }
+}
+
+/// The ops of a container in order, with whether they are visible
+#[derive(Debug, Clone)]
+pub(crate) struct VisibleIndex<Id> {
+    tree: IndexTree<Id, usize>,
+    nodes: HashMap<Id, NodeId>,
+}
+
+impl<Id> Default for VisibleIndex<Id> {
+    fn default() -> Self {
+        VisibleIndex {
+            tree: IndexTree::default(),
+            nodes: HashMap::new(),
+        }
+    }
+}
+
+impl<Id: Copy + Eq + Hash> VisibleIndex<Id> {
+    /// The number of ops, tombstones included
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn visible_len(&self) -> usize {
+        self.tree.total()
+    }
+
+    /// Insert the op `id` at `pos`, which counts the tombstones
+    pub fn insert(&mut self, pos: usize, id: Id, visible: bool) {
+        let node = self.tree.insert(pos, id, visible as usize);
+        self.nodes.insert(id, node);
+    }
+
+    pub fn set_visible(&mut self, id: Id, visible: bool) {
+        self.tree.set_metric(self.nodes[&id], visible as usize);
+    }
+
+    pub fn pos_of(&self, id: Id) -> Option<usize> {
+        self.nodes.get(&id).map(|&node| self.tree.index_of(node))
+    }
+
+    /// The number of visible ops before `pos`
+    pub fn visible_index(&self, pos: usize) -> usize {
+        self.tree.prefix(pos)
+    }
+
+    /// The position of the `index`-th visible op
+    pub fn visible_pos(&self, index: usize) -> Option<usize> {
+        match self.tree.find(index, |x| *x) {
+            (pos, _) if pos < self.len() => Some(pos),
+            _ => None,
+        }
+    }
+
+    pub fn id_at(&self, pos: usize) -> Option<Id> {
+        self.tree.node_at(pos).map(|node| *self.tree.get(node))
+    }
+}
+
+#[cfg(test)]
+mod index_tree_test {
+    use super::*;
+
+    /// Compare with a plain vec, where the metric of a value is the value itself
+    #[test]
+    fn same_as_vec() {
+        let mut tree: IndexTree<usize, usize> = IndexTree::default();
+        let mut vec: Vec<(usize, NodeId)> = Vec::new();
+        let mut seed: usize = 7;
+        let mut next = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            seed >> 33
+        };
+
+        for _ in 0..2000 {
+            let r = next();
+            if r % 3 == 0 && !vec.is_empty() {
+                let index = next() % vec.len();
+                let (value, node) = vec.remove(index);
+                assert_eq!(tree.index_of(node), index);
+                assert_eq!(tree.remove(node), value);
+            } else {
+                let index = next() % (vec.len() + 1);
+                let value = next() % 5 + 1;
+                let node = tree.insert(index, value, value);
+                vec.insert(index, (value, node));
+            }
+
+            assert_eq!(tree.len(), vec.len());
+            let index = next() % (vec.len() + 1);
+            let prefix: usize = vec[..index].iter().map(|x| x.0).sum();
+            assert_eq!(tree.prefix(index), prefix);
+            if index < vec.len() {
+                assert_eq!(tree.node_at(index), Some(vec[index].1));
+                assert_eq!(
+                    tree.find(prefix + vec[index].0 - 1, |x| *x),
+                    (index, vec[index].0 - 1)
+                );
+            }
+        }
+
+        let values: Vec<usize> = tree.iter().copied().collect();
+        assert_eq!(values, vec.iter().map(|x| x.0).collect::<Vec<_>>());
+        assert_eq!(tree.total(), values.iter().sum::<usize>());
+    }
+
+    #[test]
+    fn visible_index() {
+        let mut index: VisibleIndex<char> = VisibleIndex::default();
+        for (pos, id) in "abcdef".chars().enumerate() {
+            index.insert(pos, id, true);
+        }
+        index.set_visible('b', false);
+        index.set_visible('c', false);
+        index.insert(0, 'x', false);
+        // x a (b c) d e f
+        assert_eq!(index.len(), 7);
+        assert_eq!(index.visible_len(), 4);
+        assert_eq!(index.visible_pos(0), Some(1));
+        assert_eq!(index.visible_pos(1), Some(4));
+        assert_eq!(index.visible_pos(4), None);
+        assert_eq!(index.pos_of('d'), Some(4));
+        assert_eq!(index.visible_index(4), 1);
+        assert_eq!(index.id_at(2), Some('b'));
+
+        index.set_visible('c', true);
+        assert_eq!(index.visible_pos(1), Some(3));
+        assert_eq!(index.visible_len(), 5);
+    }
+}
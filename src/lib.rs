@@ -1,17 +1,38 @@
-//! NOTE: current implementation assume that the [crdt::ListCrdt::OpUnit] is cheap to clone
+//! NOTE: the integrate functions borrow the ops in the container via [crdt::GetOp::get_op_ref],
+//! so [crdt::ListCrdt::OpUnit] is never cloned while scanning
 //!
 //!
 //!
+#![forbid(unsafe_code)]
+pub mod causal_tree;
+pub mod codec;
 pub mod crdt;
+#[cfg(feature = "fuzzing")]
 mod dumb_common;
 pub mod fugue;
+pub mod id_span;
+mod index_tree;
+pub mod list_doc;
+pub mod local;
+pub mod marks;
+pub mod nested;
+pub mod op_log;
+pub mod peer;
+pub mod relay;
 pub mod rga;
+pub mod sync;
+pub mod text;
+pub mod tie_break;
 pub mod woot;
 pub mod yata;
 
+#[cfg(feature = "fuzzing")]
+pub mod causal_tree_dumb_impl;
 #[cfg(feature = "fuzzing")]
 pub mod fugue_dumb_impl;
 #[cfg(feature = "fuzzing")]
+pub mod network;
+#[cfg(feature = "fuzzing")]
 pub mod rga_dumb_impl;
 #[cfg(feature = "fuzzing")]
 pub mod test;
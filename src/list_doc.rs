@@ -0,0 +1,1267 @@
+//! A ready-to-use list CRDT that stores a value for every element.
+//!
+//! The merge algorithm is picked by the `A` parameter of [ListDoc], which can be
+//! [Fugue], [Yata], [Woot] or [Rga].
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    marker::PhantomData,
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::{
+    crdt::{GetOp, GetPos, ListCrdt, OpSet},
+    fugue,
+    id_span::IdSpanSet,
+    index_tree::VisibleIndex,
+    local::{self, Tombstones},
+    op_log::LogOp,
+    peer::{PeerId, PeerRegistry, Version},
+    rga,
+    tie_break::{Ascending, TieBreak},
+    woot, yata,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OpId {
+    pub client_id: PeerId,
+    pub clock: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsertOp<V> {
+    pub id: OpId,
+    pub lamport: u32,
+    pub left: Option<OpId>,
+    pub right: Option<OpId>,
+    pub value: V,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeleteOp {
+    pub id: OpId,
+    pub lamport: u32,
+    /// the insert ops whose elements are deleted, by client id
+    pub targets: IdSpanSet<PeerId>,
+}
+
+/// Deletions have their own ids, so every client's ops form a gapless sequence of clocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListOp<V> {
+    Insert(InsertOp<V>),
+    Delete(DeleteOp),
+}
+
+impl<V> ListOp<V> {
+    pub fn id(&self) -> OpId {
+        match self {
+            ListOp::Insert(op) => op.id,
+            ListOp::Delete(op) => op.id,
+        }
+    }
+
+    pub fn lamport(&self) -> u32 {
+        match self {
+            ListOp::Insert(op) => op.lamport,
+            ListOp::Delete(op) => op.lamport,
+        }
+    }
+}
+
+impl<V> LogOp for ListOp<V> {
+    type Client = PeerId;
+
+    fn client(&self) -> PeerId {
+        self.id().client_id
+    }
+
+    fn clock(&self) -> usize {
+        self.id().clock
+    }
+}
+
+/// A change to the visible elements, in the order they are applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<V> {
+    Insert { index: usize, id: OpId, value: V },
+    Delete { index: usize, id: OpId },
+}
+
+/// An index-based change between two versions, see [ListDoc::diff]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Patch<V> {
+    Insert { index: usize, values: Vec<V> },
+    Delete { index: usize, len: usize },
+}
+
+/// Whether `version` contains the op `id`
+pub fn includes(version: &Version, id: OpId) -> bool {
+    version.includes(&id.client_id, id.clock)
+}
+
+#[derive(Debug, Clone)]
+pub enum Fugue {}
+#[derive(Debug, Clone)]
+pub enum Yata {}
+#[derive(Debug, Clone)]
+pub enum Woot {}
+#[derive(Debug, Clone)]
+pub enum Rga {}
+
+pub trait Algorithm: Debug + Clone + Sized {
+    fn integrate<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        doc: &mut ListDoc<Self, V, P>,
+        op: InsertOp<V>,
+    );
+}
+
+impl Algorithm for Fugue {
+    fn integrate<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        doc: &mut ListDoc<Self, V, P>,
+        op: InsertOp<V>,
+    ) {
+        fugue::integrate::<Algo<Self, V, P>>(&mut doc.content, op, &mut ());
+    }
+}
+
+impl Algorithm for Yata {
+    fn integrate<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        doc: &mut ListDoc<Self, V, P>,
+        op: InsertOp<V>,
+    ) {
+        yata::integrate::<Algo<Self, V, P>>(&mut doc.content, op, &mut ());
+    }
+}
+
+impl Algorithm for Woot {
+    fn integrate<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        doc: &mut ListDoc<Self, V, P>,
+        op: InsertOp<V>,
+    ) {
+        let (left, right) = (op.left, op.right);
+        woot::integrate::<Algo<Self, V, P>>(&mut doc.content, op, left, right);
+    }
+}
+
+impl Algorithm for Rga {
+    fn integrate<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        doc: &mut ListDoc<Self, V, P>,
+        op: InsertOp<V>,
+    ) {
+        rga::integrate::<Algo<Self, V, P>>(&mut doc.content, op);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Elem<V> {
+    op: InsertOp<V>,
+    /// the delete ops that target this element, so older versions can still see it
+    deleted_by: Vec<OpId>,
+}
+
+impl<V> Elem<V> {
+    fn new(op: InsertOp<V>) -> Self {
+        Elem {
+            op,
+            deleted_by: Vec::new(),
+        }
+    }
+
+    fn deleted(&self) -> bool {
+        !self.deleted_by.is_empty()
+    }
+
+    fn visible_at(&self, version: &Version) -> bool {
+        includes(version, self.op.id) && !self.deleted_by.iter().any(|x| includes(version, *x))
+    }
+}
+
+/// The elements are shared between forks, until one of them is modified: the first edit of
+/// a fork copies all the elements and the index, in O(n).
+/// `index` tracks which of them are visible, for the conversions between ids and indices.
+#[derive(Clone)]
+struct Content<V> {
+    elems: Rc<Vec<Elem<V>>>,
+    index: Rc<VisibleIndex<OpId>>,
+}
+
+impl<V: Debug> Debug for Content<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.elems.fmt(f)
+    }
+}
+
+impl<V: Clone> Content<V> {
+    fn new() -> Self {
+        Content {
+            elems: Rc::new(Vec::new()),
+            index: Rc::new(VisibleIndex::default()),
+        }
+    }
+
+    fn insert(&mut self, pos: usize, op: InsertOp<V>) {
+        Rc::make_mut(&mut self.index).insert(pos, op.id, true);
+        Rc::make_mut(&mut self.elems).insert(pos, Elem::new(op));
+    }
+
+    /// Record that the delete op `id` targets the element at `pos`.
+    /// It returns whether the element was visible.
+    fn delete(&mut self, pos: usize, id: OpId) -> bool {
+        let visible = !self.elems[pos].deleted();
+        if visible {
+            Rc::make_mut(&mut self.index).set_visible(self.elems[pos].op.id, false);
+        }
+        Rc::make_mut(&mut self.elems)[pos].deleted_by.push(id);
+        visible
+    }
+
+    fn contains(&self, id: OpId) -> bool {
+        self.index.pos_of(id).is_some()
+    }
+
+    fn pos_of(&self, id: OpId) -> usize {
+        self.index.pos_of(id).unwrap()
+    }
+
+    fn len(&self) -> usize {
+        self.index.visible_len()
+    }
+
+    /// The position in `elems` of the `index`-th visible element
+    fn visible_pos(&self, index: usize) -> Option<usize> {
+        self.index.visible_pos(index)
+    }
+
+    /// The number of visible elements before `pos`
+    fn visible_index(&self, pos: usize) -> usize {
+        self.index.visible_index(pos)
+    }
+
+    fn insert_after(&mut self, left: Option<OpId>, op: InsertOp<V>) {
+        let pos = left.map(|x| self.pos_of(x) + 1).unwrap_or(0);
+        self.insert(pos, op);
+    }
+}
+
+struct Iter<'a, V> {
+    elems: &'a [Elem<V>],
+    index: usize,
+    end: Option<OpId>,
+}
+
+struct Cursor<'a, V> {
+    elems: &'a [Elem<V>],
+    pos: usize,
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = Cursor<'a, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let elem = self.elems.get(self.index)?;
+        if Some(elem.op.id) == self.end {
+            return None;
+        }
+
+        self.index += 1;
+        Some(Cursor {
+            elems: self.elems,
+            pos: self.index - 1,
+        })
+    }
+}
+
+impl<'a, V> GetOp for Cursor<'a, V> {
+    type Target = InsertOp<V>;
+
+    fn get_op_ref(&self) -> &Self::Target {
+        &self.elems[self.pos].op
+    }
+}
+
+impl<'a, V> GetPos for Cursor<'a, V> {
+    type Pos = usize;
+
+    fn get_pos(&self) -> Self::Pos {
+        self.pos
+    }
+}
+
+#[derive(Default)]
+struct IdSet(HashSet<OpId>);
+
+impl<V> OpSet<InsertOp<V>, OpId> for IdSet {
+    fn insert(&mut self, value: &InsertOp<V>) {
+        self.0.insert(value.id);
+    }
+
+    fn contain(&self, id: OpId) -> bool {
+        self.0.contains(&id)
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+struct Algo<A, V, P>(PhantomData<(A, V, P)>);
+
+impl<A: Algorithm, V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static> ListCrdt
+    for Algo<A, V, P>
+{
+    type OpUnit = InsertOp<V>;
+
+    type OpId = OpId;
+
+    type Container = Content<V>;
+
+    type Set = IdSet;
+
+    type Pos = usize;
+
+    type Cursor<'a> = Cursor<'a, V>;
+
+    type Iterator<'a> = Iter<'a, V>;
+
+    fn iter(
+        container: &Self::Container,
+        from: Option<Self::OpId>,
+        to: Option<Self::OpId>,
+    ) -> Self::Iterator<'_> {
+        Iter {
+            elems: &container.elems,
+            index: from.map(|x| container.pos_of(x) + 1).unwrap_or(0),
+            end: to,
+        }
+    }
+
+    fn id(op: &Self::OpUnit) -> Self::OpId {
+        op.id
+    }
+
+    fn cmp_id(op_a: &Self::OpUnit, op_b: &Self::OpUnit) -> Ordering {
+        P::cmp_client(&op_a.id.client_id, &op_b.id.client_id)
+            .then(op_a.id.clock.cmp(&op_b.id.clock))
+    }
+
+    fn contains(op: &Self::OpUnit, id: Self::OpId) -> bool {
+        op.id == id
+    }
+
+    fn integrated(container: &Self::Container, id: Self::OpId) -> bool {
+        container.contains(id)
+    }
+}
+
+impl<A: Algorithm, V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static> Tombstones
+    for Algo<A, V, P>
+{
+    fn get(container: &Content<V>, id: OpId) -> Option<&InsertOp<V>> {
+        let pos = container.index.pos_of(id)?;
+        Some(&container.elems[pos].op)
+    }
+
+    fn visible_id(container: &Content<V>, index: usize) -> Option<OpId> {
+        let pos = container.visible_pos(index)?;
+        container.index.id_at(pos)
+    }
+
+    fn next_id(container: &Content<V>, id: Option<OpId>) -> Option<OpId> {
+        let pos = id.map(|x| container.pos_of(x) + 1).unwrap_or(0);
+        container.elems.get(pos).map(|x| x.op.id)
+    }
+}
+
+impl<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static> fugue::Fugue for Algo<Fugue, V, P> {
+    type Context = ();
+
+    fn left_origin(op: &Self::OpUnit) -> Option<Self::OpId> {
+        op.left
+    }
+
+    fn left_origin_of_id(container: &Self::Container, op_id: &Self::OpId) -> Option<Self::OpId> {
+        container.elems[container.pos_of(*op_id)].op.left
+    }
+
+    fn right_origin(op: &Self::OpUnit) -> Option<Self::OpId> {
+        op.right
+    }
+
+    fn insert_after(container: &mut Self::Container, anchor: usize, op: Self::OpUnit, _: &mut ()) {
+        container.insert(anchor + 1, op);
+    }
+
+    fn insert_after_id(
+        container: &mut Self::Container,
+        id: Option<Self::OpId>,
+        op: Self::OpUnit,
+        _: &mut (),
+    ) {
+        container.insert_after(id, op);
+    }
+
+    fn cmp_pos(
+        container: &Self::Container,
+        op_a: Option<Self::OpId>,
+        op_b: Option<Self::OpId>,
+    ) -> Ordering {
+        // `None` is the end of the list
+        let pos = |x: Option<OpId>| x.map(|x| container.pos_of(x)).unwrap_or(usize::MAX);
+        pos(op_a).cmp(&pos(op_b))
+    }
+}
+
+impl<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static> yata::Yata for Algo<Yata, V, P> {
+    type Context = ();
+
+    fn left_origin(op: &Self::OpUnit) -> Option<Self::OpId> {
+        op.left
+    }
+
+    fn right_origin(op: &Self::OpUnit) -> Option<Self::OpId> {
+        op.right
+    }
+
+    fn insert_after(container: &mut Self::Container, anchor: usize, op: Self::OpUnit, _: &mut ()) {
+        container.insert(anchor + 1, op);
+    }
+
+    fn insert_after_id(
+        container: &mut Self::Container,
+        id: Option<Self::OpId>,
+        op: Self::OpUnit,
+        _: &mut (),
+    ) {
+        container.insert_after(id, op);
+    }
+}
+
+impl<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static> woot::Woot for Algo<Woot, V, P> {
+    fn left(op: &Self::OpUnit) -> Option<Self::OpId> {
+        op.left
+    }
+
+    fn right(op: &Self::OpUnit) -> Option<Self::OpId> {
+        op.right
+    }
+
+    fn get_pos_of(container: &Self::Container, op_id: Self::OpId) -> usize {
+        container.pos_of(op_id)
+    }
+
+    fn len(container: &Self::Container) -> usize {
+        container.elems.len()
+    }
+
+    fn insert_at(container: &mut Self::Container, op: Self::OpUnit, pos: usize) {
+        container.insert(pos, op);
+    }
+}
+
+impl<V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static> rga::Rga for Algo<Rga, V, P> {
+    type ClientId = PeerId;
+    type Lamport = u32;
+
+    fn client_id(id: Self::OpId) -> PeerId {
+        id.client_id
+    }
+
+    fn left(op: &Self::OpUnit) -> Option<Self::OpId> {
+        op.left
+    }
+
+    fn lamport(op: &Self::OpUnit) -> Self::Lamport {
+        op.lamport
+    }
+
+    fn len(container: &Self::Container) -> usize {
+        container.elems.len()
+    }
+
+    fn insert_after(container: &mut Self::Container, left: Option<Self::OpId>, op: Self::OpUnit) {
+        container.insert_after(left, op);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Readiness {
+    Ready,
+    /// it waits for this op, one of its missing dependencies
+    Waiting(OpId),
+    /// it's never applied, but its clock is consumed
+    Invalid,
+}
+
+/// `P` orders the concurrent inserts at the same position, see [crate::tie_break]
+#[derive(Debug, Clone)]
+pub struct ListDoc<A, V, P = Ascending> {
+    client_id: PeerId,
+    content: Content<V>,
+    /// the clients of the integrated ops, which index `version_vector`
+    peers: PeerRegistry<PeerId>,
+    /// exclusive end of the integrated clocks of every client
+    version_vector: Vec<usize>,
+    next_lamport: u32,
+    /// the applied delete ops, so they can be sent to the replicas that miss them
+    deletes: Rc<Vec<DeleteOp>>,
+    /// the invalid ops, which are forwarded to the replicas that miss them without being applied
+    skipped: Rc<Vec<ListOp<V>>>,
+    /// remote ops by the dependency they wait for
+    pending: HashMap<OpId, Vec<ListOp<V>>>,
+    _algorithm: PhantomData<(A, P)>,
+}
+
+impl<A: Algorithm, V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static> ListDoc<A, V, P> {
+    pub fn new(client_id: PeerId) -> Self {
+        ListDoc {
+            client_id,
+            content: Content::new(),
+            peers: PeerRegistry::new(),
+            version_vector: Vec::new(),
+            next_lamport: 0,
+            deletes: Rc::new(Vec::new()),
+            skipped: Rc::new(Vec::new()),
+            pending: HashMap::new(),
+            _algorithm: PhantomData,
+        }
+    }
+
+    pub fn client_id(&self) -> PeerId {
+        self.client_id
+    }
+
+    /// The integrated ops, keyed by client id so it can be sent to other replicas
+    pub fn version(&self) -> Version {
+        self.peers
+            .encode_version(&self.version_vector)
+            .into_iter()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&V> {
+        self.content
+            .visible_pos(index)
+            .map(|pos| &self.content.elems[pos].op.value)
+    }
+
+    /// The id of the op that inserted the `index`-th visible element
+    pub fn id_at(&self, index: usize) -> Option<OpId> {
+        let pos = self.content.visible_pos(index)?;
+        self.content.index.id_at(pos)
+    }
+
+    /// The visible index of the element inserted by `id`, `None` if it's deleted or unknown
+    pub fn index_of(&self, id: OpId) -> Option<usize> {
+        let pos = self.content.index.pos_of(id)?;
+        match self.content.elems[pos].deleted() {
+            true => None,
+            false => Some(self.content.visible_index(pos)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &V> {
+        self.content
+            .elems
+            .iter()
+            .filter(|x| !x.deleted())
+            .map(|x| &x.op.value)
+    }
+
+    /// All the elements in order, including the deleted ones, with whether they are deleted
+    pub fn elements(&self) -> impl Iterator<Item = (&InsertOp<V>, bool)> {
+        self.content.elems.iter().map(|x| (&x.op, x.deleted()))
+    }
+
+    /// The visible elements as of `version`, which should be a version this doc has seen
+    /// or a causally closed part of it. Ops that `version` doesn't include are ignored.
+    pub fn checkout(&self, version: &Version) -> Vec<&V> {
+        self.content
+            .elems
+            .iter()
+            .filter(|x| x.visible_at(version))
+            .map(|x| &x.op.value)
+            .collect()
+    }
+
+    /// The patches that turn the content at `from` into the content at `to`.
+    /// They should be applied in order, each index is relative to the result of the previous ones.
+    pub fn diff(&self, from: &Version, to: &Version) -> Vec<Patch<V>> {
+        let mut patches = Vec::new();
+        // the index in the patched content
+        let mut index = 0;
+        for elem in self.content.elems.iter() {
+            match (elem.visible_at(from), elem.visible_at(to)) {
+                (true, true) => index += 1,
+                (true, false) => match patches.last_mut() {
+                    Some(Patch::Delete { index: i, len }) if *i == index => *len += 1,
+                    _ => patches.push(Patch::Delete { index, len: 1 }),
+                },
+                (false, true) => {
+                    match patches.last_mut() {
+                        Some(Patch::Insert { index: i, values }) if *i + values.len() == index => {
+                            values.push(elem.op.value.clone())
+                        }
+                        _ => patches.push(Patch::Insert {
+                            index,
+                            values: vec![elem.op.value.clone()],
+                        }),
+                    }
+                    index += 1;
+                }
+                (false, false) => {}
+            }
+        }
+
+        patches
+    }
+
+    /// A branch of this doc that creates its new ops as `client_id`, which must not be used
+    /// by any other replica. The branches share their elements until they are modified, so
+    /// forking is O(1) but the first edit of each branch copies the elements in O(n).
+    pub fn fork(&self, client_id: PeerId) -> Self {
+        ListDoc {
+            client_id,
+            ..self.clone()
+        }
+    }
+
+    /// The ops that `version` doesn't include, in causal order
+    pub fn ops_since(&self, version: &Version) -> Vec<ListOp<V>> {
+        let inserts = self
+            .content
+            .elems
+            .iter()
+            .filter(|x| !includes(version, x.op.id))
+            .map(|x| ListOp::Insert(x.op.clone()));
+        let deletes = self
+            .deletes
+            .iter()
+            .filter(|x| !includes(version, x.id))
+            .map(|x| ListOp::Delete(x.clone()));
+        let skipped = self
+            .skipped
+            .iter()
+            .filter(|x| !includes(version, x.id()))
+            .cloned();
+        let mut ops: Vec<ListOp<V>> = inserts.chain(deletes).chain(skipped).collect();
+        // an op's lamport is greater than the ones of its dependencies
+        ops.sort_by_key(|x| (x.lamport(), x.id()));
+        ops
+    }
+
+    /// Apply the ops of `other` that this doc misses
+    pub fn merge(&mut self, other: &Self) -> Vec<Event<V>> {
+        let mut events = Vec::new();
+        for op in other.ops_since(&self.version()) {
+            events.extend(self.apply_remote(op));
+        }
+
+        events
+    }
+
+    /// Insert `values` before the `index`-th element.
+    /// It returns the ops that should be sent to the other replicas.
+    pub fn insert(&mut self, index: usize, values: impl IntoIterator<Item = V>) -> Vec<ListOp<V>> {
+        assert!(index <= self.len(), "index out of range");
+        let values: Vec<V> = values.into_iter().collect();
+        let first = self.next_id();
+        let ids: Vec<OpId> = (0..values.len())
+            .map(|i| OpId {
+                client_id: self.client_id,
+                clock: first.clock + i,
+            })
+            .collect();
+        let origins = local::insert_origins::<Algo<A, V, P>>(&self.content, index, &ids);
+        let mut ans = Vec::new();
+        for ((id, origins), value) in ids.into_iter().zip(origins).zip(values) {
+            let op = ListOp::Insert(InsertOp {
+                id,
+                lamport: self.next_lamport,
+                left: origins.left,
+                right: origins.right,
+                value,
+            });
+            self.apply(op.clone());
+            ans.push(op);
+        }
+
+        ans
+    }
+
+    /// Delete the elements in `range`.
+    /// It returns the ops that should be sent to the other replicas.
+    pub fn delete(&mut self, range: Range<usize>) -> Vec<ListOp<V>> {
+        assert!(range.end <= self.len(), "range out of bounds");
+        if range.is_empty() {
+            return Vec::new();
+        }
+
+        let start = self.content.visible_pos(range.start).unwrap();
+        let targets = self.content.elems[start..]
+            .iter()
+            .filter(|x| !x.deleted())
+            .take(range.len())
+            .map(|x| (x.op.id.client_id, x.op.id.clock))
+            .collect();
+        let op = ListOp::Delete(DeleteOp {
+            id: self.next_id(),
+            lamport: self.next_lamport,
+            targets,
+        });
+        self.apply(op.clone());
+        vec![op]
+    }
+
+    /// Ops can be applied in any order, the ones whose dependencies are
+    /// missing wait until they arrive. Ops that are already applied are ignored. The invalid
+    /// ones, whose origins or targets are delete ops, aren't applied, but their clocks are
+    /// consumed so the next ops of their clients aren't blocked.
+    ///
+    /// It returns the changes to the visible elements made by `op` and the pending ops it unblocked.
+    pub fn apply_remote(&mut self, op: ListOp<V>) -> Vec<Event<V>> {
+        let mut events = Vec::new();
+        let mut ready = vec![op];
+        while let Some(op) = ready.pop() {
+            let id = op.id();
+            if self.contains(id) {
+                continue;
+            }
+
+            match self.readiness(&op) {
+                Readiness::Waiting(dependency) => {
+                    self.pending.entry(dependency).or_default().push(op);
+                    continue;
+                }
+                Readiness::Ready => events.extend(self.apply(op)),
+                Readiness::Invalid => {
+                    self.include(id);
+                    Rc::make_mut(&mut self.skipped).push(op);
+                }
+            }
+            ready.extend(self.pending.remove(&id).into_iter().flatten());
+        }
+
+        events
+    }
+
+    fn next_id(&self) -> OpId {
+        OpId {
+            client_id: self.client_id,
+            clock: self.end(&self.client_id),
+        }
+    }
+
+    /// The exclusive end of the integrated clocks of `client_id`
+    fn end(&self, client_id: &PeerId) -> usize {
+        self.peers
+            .index_of(client_id)
+            .map(|index| self.version_vector[index])
+            .unwrap_or(0)
+    }
+
+    fn contains(&self, id: OpId) -> bool {
+        id.clock < self.end(&id.client_id)
+    }
+
+    /// The readiness of `op`, which isn't applied yet
+    fn readiness(&self, op: &ListOp<V>) -> Readiness {
+        let id = op.id();
+        if id.clock > self.end(&id.client_id) {
+            return Readiness::Waiting(OpId {
+                client_id: id.client_id,
+                clock: id.clock - 1,
+            });
+        }
+
+        // the dependencies must be applied, and be inserts
+        match op {
+            ListOp::Insert(op) => {
+                let origins: Vec<OpId> = [op.left, op.right].into_iter().flatten().collect();
+                if let Some(&origin) = origins.iter().find(|x| !self.contains(**x)) {
+                    return Readiness::Waiting(origin);
+                }
+                if !origins.iter().all(|x| self.content.contains(*x)) {
+                    return Readiness::Invalid;
+                }
+            }
+            ListOp::Delete(op) => {
+                // clocks are gapless, so the last target of a span comes last
+                if let Some((&client_id, span)) = op
+                    .targets
+                    .iter()
+                    .find(|(client_id, span)| self.end(client_id) < span.end)
+                {
+                    return Readiness::Waiting(OpId {
+                        client_id,
+                        clock: span.end - 1,
+                    });
+                }
+                if !op
+                    .targets
+                    .ids()
+                    .all(|(&client_id, clock)| self.content.contains(OpId { client_id, clock }))
+                {
+                    return Readiness::Invalid;
+                }
+            }
+        }
+
+        Readiness::Ready
+    }
+
+    /// Record the op `id` as integrated, it must be the next op of its client
+    fn include(&mut self, id: OpId) {
+        let index = self.peers.register(&id.client_id);
+        if self.version_vector.len() <= index {
+            self.version_vector.push(0);
+        }
+        self.version_vector[index] = id.clock + 1;
+    }
+
+    fn apply(&mut self, op: ListOp<V>) -> Vec<Event<V>> {
+        let id = op.id();
+        self.include(id);
+        self.next_lamport = std::cmp::max(self.next_lamport, op.lamport() + 1);
+        match op {
+            ListOp::Insert(op) => {
+                let value = op.value.clone();
+                A::integrate(self, op);
+                let pos = self.content.pos_of(id);
+                vec![Event::Insert {
+                    index: self.content.visible_index(pos),
+                    id,
+                    value,
+                }]
+            }
+            ListOp::Delete(op) => {
+                let mut events = Vec::new();
+                Rc::make_mut(&mut self.deletes).push(op.clone());
+                // in list order, like the user deleted them
+                let mut positions: Vec<usize> = op
+                    .targets
+                    .ids()
+                    .map(|(&client_id, clock)| self.content.pos_of(OpId { client_id, clock }))
+                    .collect();
+                positions.sort_unstable();
+                for pos in positions {
+                    let target = self.content.elems[pos].op.id;
+                    if !self.content.delete(pos, id) {
+                        continue;
+                    }
+
+                    events.push(Event::Delete {
+                        index: self.content.visible_index(pos),
+                        id: target,
+                    });
+                }
+
+                events
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod list_doc_test {
+    use super::*;
+    use crate::tie_break::{Descending, Hashed, Priority};
+
+    fn to_vec<A: Algorithm, P: TieBreak<PeerId> + 'static>(doc: &ListDoc<A, char, P>) -> String {
+        doc.iter().collect()
+    }
+
+    fn local_edits<A: Algorithm>() {
+        let mut doc = ListDoc::<A, char>::new(0);
+        doc.insert(0, "hello".chars());
+        doc.insert(5, " world".chars());
+        doc.insert(0, "> ".chars());
+        assert_eq!(to_vec(&doc), "> hello world");
+        doc.delete(0..2);
+        doc.delete(5..11);
+        assert_eq!(to_vec(&doc), "hello");
+        assert_eq!(doc.len(), 5);
+        assert_eq!(doc.get(1), Some(&'e'));
+        assert_eq!(doc.get(5), None);
+        doc.insert(5, "!".chars());
+        assert_eq!(to_vec(&doc), "hello!");
+    }
+
+    /// Three replicas edit concurrently, then receive the ops of the others
+    /// in different orders, some of them before their dependencies.
+    fn converge<A: Algorithm, P: TieBreak<PeerId> + 'static>() {
+        let mut a = ListDoc::<A, char, P>::new(0);
+        let mut b = ListDoc::<A, char, P>::new(1);
+        let mut c = ListDoc::<A, char, P>::new(2);
+        let base = a.insert(0, "abc".chars());
+        for op in base.iter() {
+            b.apply_remote(op.clone());
+            c.apply_remote(op.clone());
+        }
+
+        let mut ops_a = a.insert(1, "12".chars());
+        ops_a.extend(a.delete(0..1));
+        let mut ops_b = b.insert(1, "xy".chars());
+        ops_b.extend(b.delete(2..4));
+        let mut ops_c = c.insert(3, "!".chars());
+        ops_c.extend(c.insert(0, "_".chars()));
+
+        for op in ops_b.iter().chain(ops_c.iter()).rev() {
+            a.apply_remote(op.clone());
+        }
+        for op in ops_c.iter().chain(ops_a.iter()) {
+            b.apply_remote(op.clone());
+        }
+        for op in ops_a.iter().rev().chain(ops_b.iter()) {
+            c.apply_remote(op.clone());
+        }
+        // duplicates are ignored
+        for op in base.iter().chain(ops_b.iter()) {
+            a.apply_remote(op.clone());
+        }
+
+        assert_eq!(to_vec(&a), to_vec(&b));
+        assert_eq!(to_vec(&a), to_vec(&c));
+        assert_eq!(a.len(), 6);
+    }
+
+    /// Record the content after every edit, then check out every version
+    /// and diff every pair of them
+    fn time_travel<A: Algorithm>() {
+        let mut a = ListDoc::<A, char>::new(0);
+        let mut b = ListDoc::<A, char>::new(1);
+        let mut history = Vec::new();
+        let mut record = |doc: &ListDoc<A, char>| {
+            history.push((doc.version(), to_vec(doc)));
+        };
+
+        record(&a);
+        for op in a.insert(0, "hello".chars()) {
+            b.apply_remote(op);
+        }
+        record(&a);
+        let ops_b = b.delete(1..3);
+        a.insert(5, " world".chars());
+        record(&a);
+        // both delete the same "l"
+        a.delete(2..3);
+        record(&a);
+        for op in ops_b {
+            a.apply_remote(op);
+        }
+        record(&a);
+        a.insert(1, "ipp".chars());
+        record(&a);
+
+        let live = to_vec(&a);
+        for (version, content) in history.iter() {
+            assert_eq!(
+                &a.checkout(version).into_iter().collect::<String>(),
+                content
+            );
+        }
+        for (from, old) in history.iter() {
+            for (to, new) in history.iter() {
+                let mut patched: Vec<char> = old.chars().collect();
+                for patch in a.diff(from, to) {
+                    match patch {
+                        Patch::Insert { index, values } => {
+                            patched.splice(index..index, values);
+                        }
+                        Patch::Delete { index, len } => {
+                            patched.drain(index..index + len);
+                        }
+                    }
+                }
+                assert_eq!(&patched.into_iter().collect::<String>(), new);
+            }
+        }
+        assert_eq!(to_vec(&a), live);
+        assert_eq!(live, "hipplo world");
+    }
+
+    /// Branches are edited separately, then merged in different orders
+    fn fork_and_merge<A: Algorithm>() {
+        let mut base = ListDoc::<A, char>::new(0);
+        base.insert(0, "hello world".chars());
+        let mut branches: Vec<_> = (1..=3).map(|i| base.fork(i)).collect();
+        assert!(Rc::ptr_eq(&base.content.elems, &branches[0].content.elems));
+
+        branches[0].insert(5, ",".chars());
+        branches[0].delete(0..1);
+        branches[1].delete(3..9);
+        branches[1].insert(0, "> ".chars());
+        branches[2].insert(11, "!".chars());
+        branches[2].insert(5, " there".chars());
+        assert_eq!(to_vec(&base), "hello world");
+
+        let orders = [[0, 1, 2], [2, 1, 0], [1, 2, 0]];
+        let mut merged = Vec::new();
+        for (i, order) in orders.iter().enumerate() {
+            let mut doc = base.fork(4 + i as PeerId);
+            let mut replayed: Vec<char> = to_vec(&doc).chars().collect();
+            for &j in order.iter().chain(order.iter()) {
+                for event in doc.merge(&branches[j]) {
+                    match event {
+                        Event::Insert { index, value, .. } => replayed.insert(index, value),
+                        Event::Delete { index, .. } => {
+                            replayed.remove(index);
+                        }
+                    }
+                }
+            }
+
+            assert_eq!(replayed.into_iter().collect::<String>(), to_vec(&doc));
+            merged.push(to_vec(&doc));
+        }
+
+        assert_eq!(merged[0], merged[1]);
+        assert_eq!(merged[0], merged[2]);
+        // the concurrent inserts after "o" can be in either order
+        assert!(merged[0] == "> el, thereld!" || merged[0] == "> el there,ld!");
+        // a branch only receives the ops it misses
+        assert_eq!(base.ops_since(&base.version()), vec![]);
+        assert_eq!(branches[2].ops_since(&base.version()).len(), 7);
+    }
+
+    #[test]
+    fn diff_merges_runs() {
+        let mut doc = ListDoc::<Fugue, char>::new(0);
+        doc.insert(0, "abcdef".chars());
+        let from = doc.version();
+        doc.delete(1..4);
+        doc.insert(1, "xy".chars());
+        let to = doc.version();
+        assert_eq!(
+            doc.diff(&from, &to),
+            vec![
+                Patch::Insert {
+                    index: 1,
+                    values: vec!['x', 'y']
+                },
+                Patch::Delete { index: 3, len: 3 },
+            ]
+        );
+    }
+
+    #[test]
+    fn ids_and_indices() {
+        let mut doc = ListDoc::<Yata, char>::new(0);
+        doc.insert(0, "abcdef".chars());
+        doc.delete(1..3);
+        let ids: Vec<OpId> = (0..doc.len()).map(|i| doc.id_at(i).unwrap()).collect();
+        assert_eq!(
+            ids.iter().map(|x| x.clock).collect::<Vec<_>>(),
+            vec![0, 3, 4, 5]
+        );
+        for (index, id) in ids.iter().enumerate() {
+            assert_eq!(doc.index_of(*id), Some(index));
+        }
+        assert_eq!(doc.id_at(4), None);
+        let (deleted, _) = doc.elements().find(|(_, deleted)| *deleted).unwrap();
+        assert_eq!(doc.index_of(deleted.id), None);
+    }
+
+    #[test]
+    fn random_peer_ids() {
+        let (x, y) = (u128::MAX, 1 << 100);
+        let mut a = ListDoc::<Fugue, char>::new(x);
+        let mut b = ListDoc::<Fugue, char>::new(y);
+        a.insert(0, "ab".chars());
+        b.insert(0, "c".chars());
+        b.merge(&a);
+        b.delete(0..1);
+        a.merge(&b);
+        assert_eq!(to_vec(&a), to_vec(&b));
+        assert_eq!(a.version(), [(x, 2), (y, 2)].into_iter().collect());
+        assert_eq!(a.version(), b.version());
+        assert_eq!(a.ops_since(&[(x, 2)].into_iter().collect()).len(), 2);
+    }
+
+    #[test]
+    fn invalid_ops_are_skipped() {
+        let mut a = ListDoc::<Fugue, char>::new(0);
+        let mut ops = a.insert(0, "ab".chars());
+        ops.extend(a.delete(0..1));
+        let delete_id = ops[2].id();
+        let id = |client_id, clock| OpId { client_id, clock };
+        let bogus_delete = ListOp::Delete(DeleteOp {
+            id: id(1, 0),
+            lamport: 3,
+            targets: [(0, 1), (0, 2)].into_iter().collect(),
+        });
+        let bogus_insert = ListOp::Insert(InsertOp {
+            id: id(2, 0),
+            lamport: 3,
+            left: Some(delete_id),
+            right: None,
+            value: 'x',
+        });
+
+        // the next op of a client that sent an invalid one isn't blocked
+        let next = ListOp::Insert(InsertOp {
+            id: id(1, 1),
+            lamport: 4,
+            left: None,
+            right: None,
+            value: 'y',
+        });
+
+        // they arrive before their dependencies, then are skipped when these arrive
+        let mut b = ListDoc::<Fugue, char>::new(3);
+        assert!(b.apply_remote(next.clone()).is_empty());
+        assert!(b.apply_remote(bogus_delete.clone()).is_empty());
+        assert!(b.apply_remote(bogus_insert.clone()).is_empty());
+        for op in ops.iter().cloned() {
+            b.apply_remote(op);
+        }
+        assert!(a.apply_remote(bogus_delete).is_empty());
+        assert!(a.apply_remote(bogus_insert).is_empty());
+        assert_eq!(a.apply_remote(next).len(), 1);
+        for doc in [&a, &b] {
+            assert_eq!(to_vec(doc), "by");
+            assert_eq!(
+                doc.version(),
+                [(0, 3), (1, 2), (2, 1)].into_iter().collect()
+            );
+            assert!(doc.pending.is_empty());
+        }
+
+        // the skipped ops are forwarded, so the replicas that only talk to `a` get past them
+        let mut c = ListDoc::<Fugue, char>::new(4);
+        c.merge(&a);
+        assert_eq!(to_vec(&c), "by");
+        assert_eq!(c.version(), a.version());
+    }
+
+    #[test]
+    fn events_replay_remote_changes() {
+        let mut a = ListDoc::<Fugue, char>::new(0);
+        let mut b = ListDoc::<Fugue, char>::new(1);
+        let mut ops = a.insert(0, "hello".chars());
+        ops.extend(b.insert(0, "world".chars()));
+        ops.extend(a.delete(1..3));
+        ops.extend(b.delete(0..1));
+
+        let mut replica = ListDoc::<Fugue, char>::new(2);
+        let mut replayed = Vec::new();
+        // deliver them backwards, so most of them wait for their dependencies first
+        for op in ops.into_iter().rev() {
+            for event in replica.apply_remote(op) {
+                match event {
+                    Event::Insert { index, value, .. } => replayed.insert(index, value),
+                    Event::Delete { index, .. } => {
+                        replayed.remove(index);
+                    }
+                }
+            }
+        }
+
+        assert_eq!(replayed.iter().collect::<String>(), to_vec(&replica));
+        assert_eq!(replica.len(), 7);
+    }
+
+    #[test]
+    fn local() {
+        local_edits::<Fugue>();
+        local_edits::<Yata>();
+        local_edits::<Woot>();
+        local_edits::<Rga>();
+    }
+
+    #[test]
+    fn concurrent() {
+        converge::<Fugue, Ascending>();
+        converge::<Yata, Ascending>();
+        converge::<Woot, Ascending>();
+        converge::<Rga, Ascending>();
+    }
+
+    fn every_policy<A: Algorithm>() {
+        converge::<A, Descending>();
+        converge::<A, Priority<1>>();
+        converge::<A, Hashed>();
+    }
+
+    #[test]
+    fn policies() {
+        every_policy::<Fugue>();
+        every_policy::<Yata>();
+        every_policy::<Woot>();
+        every_policy::<Rga>();
+    }
+
+    /// The concurrent inserts of 0 and 1 at the same position
+    fn tie<A: Algorithm, P: TieBreak<PeerId> + 'static>() -> String {
+        let mut a = ListDoc::<A, char, P>::new(0);
+        let mut b = ListDoc::<A, char, P>::new(1);
+        let ops = a.insert(0, "a".chars());
+        for op in b.insert(0, "b".chars()) {
+            a.apply_remote(op);
+        }
+        for op in ops {
+            b.apply_remote(op);
+        }
+
+        assert_eq!(to_vec(&a), to_vec(&b));
+        to_vec(&a)
+    }
+
+    #[test]
+    fn server_wins_ties() {
+        fn check<A: Algorithm>() {
+            assert_ne!(tie::<A, Ascending>(), tie::<A, Descending>());
+            // treating 0 as the greatest client is the same as reversing 0 and 1
+            assert_eq!(tie::<A, Priority<0>>(), tie::<A, Descending>());
+            assert_eq!(tie::<A, Priority<1>>(), tie::<A, Ascending>());
+        }
+
+        check::<Fugue>();
+        check::<Yata>();
+        check::<Woot>();
+        check::<Rga>();
+    }
+
+    #[test]
+    fn branches() {
+        fork_and_merge::<Fugue>();
+        fork_and_merge::<Yata>();
+        fork_and_merge::<Woot>();
+        fork_and_merge::<Rga>();
+    }
+
+    #[test]
+    fn history() {
+        time_travel::<Fugue>();
+        time_travel::<Yata>();
+        time_travel::<Woot>();
+        time_travel::<Rga>();
+    }
+
+    #[test]
+    fn fugue_does_not_interleave() {
+        let mut a = ListDoc::<Fugue, char>::new(0);
+        let mut b = ListDoc::<Fugue, char>::new(1);
+        let ops_a = a.insert(0, "abc".chars());
+        let ops_b = b.insert(0, "xyz".chars());
+        for op in ops_a {
+            b.apply_remote(op);
+        }
+        for op in ops_b {
+            a.apply_remote(op);
+        }
+
+        assert_eq!(to_vec(&a), to_vec(&b));
+        assert!(to_vec(&a) == "abcxyz" || to_vec(&a) == "xyzabc");
+    }
+}
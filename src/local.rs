@@ -0,0 +1,74 @@
+//! Create local inserts from a visible index.
+//!
+//! Users see the visible elements only, but an insert is positioned by its origins, which
+//! are ops in the container that may be deleted. [insert_origins] turns the index into them.
+//! An insert goes right after the visible element before `index`, so its left origin is that
+//! element and its right origin is whatever follows it in the container, tombstones included.
+//! WOOTO also needs the degrees of the run, see [woot_degrees]. RGA only needs the left
+//! origins, its lamports come from the clock of the replica.
+
+use crate::{crdt::ListCrdt, woot::WootO};
+
+/// Containers that keep the deleted ops as tombstones. They should index the ops, so that
+/// the functions of this module don't scan the container.
+pub trait Tombstones: ListCrdt {
+    /// The op `id`, deleted or not
+    fn get(container: &Self::Container, id: Self::OpId) -> Option<&Self::OpUnit>;
+    /// The id of the `index`-th visible op
+    fn visible_id(container: &Self::Container, index: usize) -> Option<Self::OpId>;
+    /// The id of the op right after `id`, tombstones included, or of the first op if `id`
+    /// is `None`
+    fn next_id(container: &Self::Container, id: Option<Self::OpId>) -> Option<Self::OpId>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Origins<Id> {
+    pub left: Option<Id>,
+    pub right: Option<Id>,
+}
+
+/// The origins of a run of ops inserted before the `index`-th visible element, where `ids`
+/// are the ids of the new ops. Every op of the run is inserted after the previous one.
+///
+/// # Panics
+///
+/// If `index` is greater than the number of visible elements
+pub fn insert_origins<T: Tombstones>(
+    container: &T::Container,
+    index: usize,
+    ids: &[T::OpId],
+) -> Vec<Origins<T::OpId>> {
+    let left = match index {
+        0 => None,
+        _ => Some(T::visible_id(container, index - 1).expect("index out of range")),
+    };
+    let right = T::next_id(container, left);
+    let lefts = std::iter::once(left).chain(ids.iter().map(|x| Some(*x)));
+    lefts
+        .take(ids.len())
+        .map(|left| Origins { left, right })
+        .collect()
+}
+
+/// The WOOTO degrees of the ops of a run with `origins`, see [WootO]
+pub fn woot_degrees<T: WootO + Tombstones>(
+    container: &T::Container,
+    origins: &[Origins<T::OpId>],
+) -> Vec<usize> {
+    let degree_of = |id: Option<T::OpId>| match id {
+        Some(id) => T::degree(T::get(container, id).expect("the origin is not in the container")),
+        None => 0,
+    };
+
+    let mut degrees: Vec<usize> = Vec::with_capacity(origins.len());
+    let right = origins.first().map(|x| degree_of(x.right)).unwrap_or(0);
+    for (i, origins) in origins.iter().enumerate() {
+        let left = match i {
+            0 => degree_of(origins.left),
+            _ => degrees[i - 1],
+        };
+        degrees.push(std::cmp::max(left, right) + 1);
+    }
+
+    degrees
+}
@@ -0,0 +1,323 @@
+//! Rich-text formatting marks in the style of Peritext.
+//!
+//! A mark doesn't store indexes. Its start and end are anchored on the gaps next to
+//! the elements, so it keeps covering the same elements while the list is edited
+//! concurrently. Whether the elements inserted at its boundaries get the mark depends
+//! on which gaps it's anchored on, see [Expand].
+//!
+//! When several mark ops with the same key cover an element, the one with the greatest
+//! `(lamport, client_id)` wins.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Debug,
+    ops::Range,
+};
+
+use crate::{
+    list_doc::{Algorithm, ListDoc, OpId},
+    peer::PeerId,
+    tie_break::TieBreak,
+};
+
+/// A gap in the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    /// Before all the elements
+    Start,
+    Before(OpId),
+    After(OpId),
+    /// After all the elements
+    End,
+}
+
+/// Whether the elements inserted at the boundaries of a mark get the mark.
+/// E.g. bold usually expands after, while links don't expand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expand {
+    None,
+    Before,
+    After,
+    Both,
+}
+
+/// Mark ops are numbered separately from the list ops
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkOp<T> {
+    pub id: OpId,
+    pub lamport: u32,
+    pub start: Anchor,
+    pub end: Anchor,
+    pub key: String,
+    /// `None` removes the mark
+    pub value: Option<T>,
+}
+
+/// Consecutive visible elements with the same marks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span<T> {
+    pub range: Range<usize>,
+    pub marks: BTreeMap<String, T>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Marks<T> {
+    client_id: PeerId,
+    next_clock: usize,
+    next_lamport: u32,
+    /// sorted by `(lamport, client_id)`
+    ops: Vec<MarkOp<T>>,
+    ids: HashSet<OpId>,
+}
+
+impl<T: Clone + PartialEq + Debug> Marks<T> {
+    pub fn new(client_id: PeerId) -> Self {
+        Marks {
+            client_id,
+            next_clock: 0,
+            next_lamport: 0,
+            ops: Vec::new(),
+            ids: HashSet::new(),
+        }
+    }
+
+    /// Add the mark `key` to the visible elements in `range` of `doc`.
+    /// It returns the op that should be sent to the other replicas.
+    ///
+    /// # Panics
+    ///
+    /// If `range` is empty or out of bounds
+    pub fn mark<A: Algorithm, V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        &mut self,
+        doc: &ListDoc<A, V, P>,
+        range: Range<usize>,
+        key: &str,
+        value: T,
+        expand: Expand,
+    ) -> MarkOp<T> {
+        self.new_op(doc, range, key, Some(value), expand)
+    }
+
+    /// Remove the mark `key` from the visible elements in `range` of `doc`.
+    /// It returns the op that should be sent to the other replicas.
+    ///
+    /// # Panics
+    ///
+    /// If `range` is empty or out of bounds
+    pub fn unmark<A: Algorithm, V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        &mut self,
+        doc: &ListDoc<A, V, P>,
+        range: Range<usize>,
+        key: &str,
+        expand: Expand,
+    ) -> MarkOp<T> {
+        self.new_op(doc, range, key, None, expand)
+    }
+
+    /// Ops that are already applied are ignored
+    pub fn apply_remote(&mut self, op: MarkOp<T>) {
+        if self.ids.contains(&op.id) {
+            return;
+        }
+
+        self.next_lamport = std::cmp::max(self.next_lamport, op.lamport + 1);
+        self.ids.insert(op.id);
+        let key = |x: &MarkOp<T>| (x.lamport, x.id.client_id);
+        let index = self.ops.partition_point(|x| key(x) < key(&op));
+        self.ops.insert(index, op);
+    }
+
+    /// The marks of the visible elements of `doc`.
+    /// Marks anchored on elements that `doc` doesn't have yet are ignored.
+    pub fn spans<A: Algorithm, V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        &self,
+        doc: &ListDoc<A, V, P>,
+    ) -> Vec<Span<T>> {
+        // Element `i` is at slot `2i + 2`, the gaps around it are at `2i + 1` and `2i + 3`
+        let pos: HashMap<OpId, usize> = doc
+            .elements()
+            .enumerate()
+            .map(|(i, (op, _))| (op.id, i))
+            .collect();
+        let slot = |anchor: Anchor| match anchor {
+            Anchor::Start => Some(0),
+            Anchor::Before(id) => pos.get(&id).map(|x| 2 * x + 1),
+            Anchor::After(id) => pos.get(&id).map(|x| 2 * x + 3),
+            Anchor::End => Some(usize::MAX),
+        };
+        let ranges: Vec<(usize, usize, &MarkOp<T>)> = self
+            .ops
+            .iter()
+            .filter_map(|op| Some((slot(op.start)?, slot(op.end)?, op)))
+            .collect();
+
+        let mut spans: Vec<Span<T>> = Vec::new();
+        let visible = doc
+            .elements()
+            .enumerate()
+            .filter(|(_, (_, deleted))| !deleted)
+            .map(|(i, _)| 2 * i + 2);
+        for (index, elem) in visible.enumerate() {
+            let mut marks = BTreeMap::new();
+            for (start, end, op) in ranges.iter() {
+                if *start < elem && elem < *end {
+                    match &op.value {
+                        Some(value) => marks.insert(op.key.clone(), value.clone()),
+                        None => marks.remove(&op.key),
+                    };
+                }
+            }
+
+            match spans.last_mut() {
+                Some(last) if last.marks == marks => last.range.end = index + 1,
+                _ => spans.push(Span {
+                    range: index..index + 1,
+                    marks,
+                }),
+            }
+        }
+
+        spans
+    }
+
+    fn new_op<A: Algorithm, V: Clone + Debug + 'static, P: TieBreak<PeerId> + 'static>(
+        &mut self,
+        doc: &ListDoc<A, V, P>,
+        range: Range<usize>,
+        key: &str,
+        value: Option<T>,
+        expand: Expand,
+    ) -> MarkOp<T> {
+        assert!(
+            range.start < range.end && range.end <= doc.len(),
+            "invalid range"
+        );
+        let id_at = |index| doc.id_at(index).unwrap();
+        let start = match expand {
+            Expand::Before | Expand::Both => range
+                .start
+                .checked_sub(1)
+                .map(|x| Anchor::After(id_at(x)))
+                .unwrap_or(Anchor::Start),
+            Expand::None | Expand::After => Anchor::Before(id_at(range.start)),
+        };
+        let end = match expand {
+            Expand::After | Expand::Both => doc
+                .id_at(range.end)
+                .map(Anchor::Before)
+                .unwrap_or(Anchor::End),
+            Expand::None | Expand::Before => Anchor::After(id_at(range.end - 1)),
+        };
+
+        let op = MarkOp {
+            id: OpId {
+                client_id: self.client_id,
+                clock: self.next_clock,
+            },
+            lamport: self.next_lamport,
+            start,
+            end,
+            key: key.to_string(),
+            value,
+        };
+        self.next_clock += 1;
+        self.apply_remote(op.clone());
+        op
+    }
+}
+
+#[cfg(test)]
+mod marks_test {
+    use super::*;
+    use crate::{
+        list_doc::{Fugue, Rga, Woot, Yata},
+        tie_break::Descending,
+    };
+
+    fn text_of<A: Algorithm>(doc: &ListDoc<A, char>, span: &Span<bool>) -> String {
+        doc.iter()
+            .skip(span.range.start)
+            .take(span.range.len())
+            .collect()
+    }
+
+    #[test]
+    fn expand() {
+        let mut doc = ListDoc::<Fugue, char>::new(0);
+        let mut marks = Marks::new(0);
+        doc.insert(0, "hello world".chars());
+        marks.mark(&doc, 0..5, "bold", true, Expand::After);
+        marks.mark(&doc, 6..11, "link", true, Expand::None);
+        doc.insert(5, "!".chars());
+        doc.insert(0, "<".chars());
+        doc.insert(13, "?".chars());
+
+        let spans = marks.spans(&doc);
+        let texts: Vec<_> = spans.iter().map(|x| text_of(&doc, x)).collect();
+        assert_eq!(texts, vec!["<", "hello!", " ", "world", "?"]);
+        assert!(spans[0].marks.is_empty());
+        assert_eq!(spans[1].marks.keys().collect::<Vec<_>>(), vec!["bold"]);
+        assert_eq!(spans[3].marks.keys().collect::<Vec<_>>(), vec!["link"]);
+        assert!(spans[4].marks.is_empty());
+    }
+
+    #[test]
+    fn any_tie_break() {
+        let mut doc = ListDoc::<Fugue, char, Descending>::new(0);
+        let mut marks = Marks::new(0);
+        doc.insert(0, "hello".chars());
+        marks.mark(&doc, 1..3, "bold", true, Expand::None);
+        let spans = marks.spans(&doc);
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[1].range, 1..3);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid range")]
+    fn empty_range() {
+        let mut doc = ListDoc::<Fugue, char>::new(0);
+        doc.insert(0, "hello".chars());
+        Marks::new(0).mark(&doc, 2..2, "bold", true, Expand::None);
+    }
+
+    /// a bolds everything while b inserts text and unbolds the middle concurrently.
+    /// Both have lamport 0, so b's unmark wins because of its greater client id.
+    fn concurrent_mark_and_unmark<A: Algorithm>() {
+        let mut doc_a = ListDoc::<A, char>::new(0);
+        let mut doc_b = ListDoc::<A, char>::new(1);
+        let mut marks_a = Marks::new(0);
+        let mut marks_b = Marks::new(1);
+        for op in doc_a.insert(0, "hello world".chars()) {
+            doc_b.apply_remote(op);
+        }
+
+        let bold = marks_a.mark(&doc_a, 0..11, "bold", true, Expand::After);
+        let text_b = doc_b.insert(5, "XY".chars());
+        let unbold = marks_b.unmark(&doc_b, 3..6, "bold", Expand::None);
+
+        // a receives the unmark before the text it ends on
+        marks_a.apply_remote(unbold.clone());
+        assert_eq!(marks_a.spans(&doc_a).len(), 1);
+        for op in text_b {
+            doc_a.apply_remote(op);
+        }
+        marks_b.apply_remote(bold.clone());
+        marks_b.apply_remote(bold);
+
+        let spans = marks_a.spans(&doc_a);
+        assert_eq!(spans, marks_b.spans(&doc_b));
+        let texts: Vec<_> = spans.iter().map(|x| text_of(&doc_a, x)).collect();
+        assert_eq!(texts, vec!["hel", "loX", "Y world"]);
+        assert_eq!(spans[0].marks.get("bold"), Some(&true));
+        assert!(spans[1].marks.is_empty());
+        assert_eq!(spans[2].marks.get("bold"), Some(&true));
+    }
+
+    #[test]
+    fn concurrent() {
+        concurrent_mark_and_unmark::<Fugue>();
+        concurrent_mark_and_unmark::<Yata>();
+        concurrent_mark_and_unmark::<Woot>();
+        concurrent_mark_and_unmark::<Rga>();
+    }
+}
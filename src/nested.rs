@@ -0,0 +1,274 @@
+//! Lists whose elements can be lists, like outlines or JSON arrays.
+//!
+//! Every list is a [ListDoc] with its own clocks. A child list is created by an insert
+//! op in its parent, and it's identified by the path of these ops from the root.
+//! The ops of a child wait until the op that created it is applied. When the element
+//! of a child is deleted, the child is hidden, but its ops are still integrated.
+
+use std::{collections::HashMap, fmt::Debug, ops::Range};
+
+use crate::{
+    list_doc::{Algorithm, Event, ListDoc, ListOp, OpId},
+    peer::PeerId,
+};
+
+/// The path of the insert ops that created the list, the root is the empty path
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ContainerId(pub Vec<OpId>);
+
+impl ContainerId {
+    pub fn root() -> Self {
+        ContainerId(Vec::new())
+    }
+
+    pub fn child(&self, id: OpId) -> Self {
+        let mut path = self.0.clone();
+        path.push(id);
+        ContainerId(path)
+    }
+
+    /// The parent and the op in it that created this list
+    pub fn parent(&self) -> Option<(ContainerId, OpId)> {
+        let (last, path) = self.0.split_last()?;
+        Some((ContainerId(path.to_vec()), *last))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value<V> {
+    Leaf(V),
+    /// The list's content is in the child identified by this element's op id
+    List,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NestedOp<V> {
+    pub container: ContainerId,
+    pub op: ListOp<Value<V>>,
+}
+
+/// The visible content, see [NestedDoc::to_tree]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node<V> {
+    Leaf(V),
+    List(Vec<Node<V>>),
+}
+
+#[derive(Debug, Clone)]
+pub struct NestedDoc<A, V> {
+    client_id: PeerId,
+    lists: HashMap<ContainerId, ListDoc<A, Value<V>>>,
+    /// remote ops whose list isn't created yet
+    pending: Vec<NestedOp<V>>,
+}
+
+impl<A: Algorithm, V: Clone + Debug + 'static> NestedDoc<A, V> {
+    pub fn new(client_id: PeerId) -> Self {
+        let mut lists = HashMap::new();
+        lists.insert(ContainerId::root(), ListDoc::new(client_id));
+        NestedDoc {
+            client_id,
+            lists,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn client_id(&self) -> PeerId {
+        self.client_id
+    }
+
+    /// The list `id`, including the deleted ones. `None` if it isn't created yet.
+    pub fn list(&self, id: &ContainerId) -> Option<&ListDoc<A, Value<V>>> {
+        self.lists.get(id)
+    }
+
+    /// Insert `values` before the `index`-th element of `container`.
+    /// It returns the ops that should be sent to the other replicas.
+    pub fn insert(
+        &mut self,
+        container: &ContainerId,
+        index: usize,
+        values: impl IntoIterator<Item = V>,
+    ) -> Vec<NestedOp<V>> {
+        let ops = self
+            .list_mut(container)
+            .insert(index, values.into_iter().map(Value::Leaf));
+        Self::wrap(container, ops)
+    }
+
+    /// Create an empty list before the `index`-th element of `container`.
+    /// It returns the id of the new list and the op that should be sent to the other replicas.
+    pub fn insert_list(
+        &mut self,
+        container: &ContainerId,
+        index: usize,
+    ) -> (ContainerId, NestedOp<V>) {
+        let op = self
+            .list_mut(container)
+            .insert(index, [Value::List])
+            .pop()
+            .unwrap();
+        let child = container.child(op.id());
+        self.lists
+            .insert(child.clone(), ListDoc::new(self.client_id));
+        (
+            child,
+            NestedOp {
+                container: container.clone(),
+                op,
+            },
+        )
+    }
+
+    /// Delete the elements in `range` of `container`, the deleted lists are hidden.
+    /// It returns the ops that should be sent to the other replicas.
+    pub fn delete(&mut self, container: &ContainerId, range: Range<usize>) -> Vec<NestedOp<V>> {
+        let ops = self.list_mut(container).delete(range);
+        Self::wrap(container, ops)
+    }
+
+    /// Ops can be applied in any order, the ones whose list or dependencies are
+    /// missing wait until they arrive. Ops that are already applied are ignored.
+    ///
+    /// It returns the changes to the elements of every list, including the hidden ones.
+    pub fn apply_remote(&mut self, op: NestedOp<V>) -> Vec<(ContainerId, Event<Value<V>>)> {
+        let mut ans = Vec::new();
+        self.pending.push(op);
+        while let Some(index) = self
+            .pending
+            .iter()
+            .position(|x| self.lists.contains_key(&x.container))
+        {
+            let NestedOp { container, op } = self.pending.swap_remove(index);
+            let events = self.lists.get_mut(&container).unwrap().apply_remote(op);
+            for event in events {
+                if let Event::Insert {
+                    id,
+                    value: Value::List,
+                    ..
+                } = &event
+                {
+                    self.lists
+                        .insert(container.child(*id), ListDoc::new(self.client_id));
+                }
+
+                ans.push((container.clone(), event));
+            }
+        }
+
+        ans
+    }
+
+    /// The visible content of the root list
+    pub fn to_tree(&self) -> Vec<Node<V>> {
+        self.tree_of(&ContainerId::root())
+    }
+
+    fn tree_of(&self, container: &ContainerId) -> Vec<Node<V>> {
+        self.lists[container]
+            .elements()
+            .filter(|(_, deleted)| !deleted)
+            .map(|(op, _)| match &op.value {
+                Value::Leaf(value) => Node::Leaf(value.clone()),
+                Value::List => Node::List(self.tree_of(&container.child(op.id))),
+            })
+            .collect()
+    }
+
+    fn list_mut(&mut self, container: &ContainerId) -> &mut ListDoc<A, Value<V>> {
+        self.lists.get_mut(container).expect("unknown container")
+    }
+
+    fn wrap(container: &ContainerId, ops: Vec<ListOp<Value<V>>>) -> Vec<NestedOp<V>> {
+        ops.into_iter()
+            .map(|op| NestedOp {
+                container: container.clone(),
+                op,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod nested_test {
+    use super::*;
+    use crate::list_doc::{Fugue, Rga, Woot, Yata};
+
+    use Node::{Leaf, List};
+
+    #[test]
+    fn child_ops_wait_for_their_list() {
+        let mut a = NestedDoc::<Fugue, char>::new(0);
+        let root = ContainerId::root();
+        let mut ops = a.insert(&root, 0, "ab".chars());
+        let (outer, op) = a.insert_list(&root, 1);
+        ops.push(op);
+        ops.extend(a.insert(&outer, 0, "c".chars()));
+        let (inner, op) = a.insert_list(&outer, 1);
+        ops.push(op);
+        ops.extend(a.insert(&inner, 0, "de".chars()));
+        assert_eq!(inner.parent(), Some((outer.clone(), inner.0[1])));
+
+        let expected = vec![
+            Leaf('a'),
+            List(vec![Leaf('c'), List(vec![Leaf('d'), Leaf('e')])]),
+            Leaf('b'),
+        ];
+        assert_eq!(a.to_tree(), expected);
+
+        let mut b = NestedDoc::<Fugue, char>::new(1);
+        for op in ops.into_iter().rev() {
+            b.apply_remote(op);
+        }
+        assert_eq!(b.to_tree(), expected);
+    }
+
+    /// a edits a child list while b deletes it and creates another one next to it
+    fn concurrent_edits<A: Algorithm>() {
+        let root = ContainerId::root();
+        let mut a = NestedDoc::<A, char>::new(0);
+        let mut b = NestedDoc::<A, char>::new(1);
+        let (list, op) = a.insert_list(&root, 0);
+        let mut ops_a = vec![op];
+        ops_a.extend(a.insert(&list, 0, "xy".chars()));
+        for op in ops_a.drain(..) {
+            b.apply_remote(op);
+        }
+
+        ops_a.extend(a.insert(&list, 1, "1".chars()));
+        ops_a.extend(a.insert(&root, 1, "!".chars()));
+        let mut ops_b = b.delete(&root, 0..1);
+        let (other, op) = b.insert_list(&root, 0);
+        ops_b.push(op);
+        ops_b.extend(b.insert(&other, 0, "z".chars()));
+
+        for op in ops_b {
+            a.apply_remote(op);
+        }
+        for op in ops_a {
+            b.apply_remote(op);
+        }
+
+        assert_eq!(a.to_tree(), b.to_tree());
+        assert_eq!(a.to_tree(), vec![List(vec![Leaf('z')]), Leaf('!')]);
+        // the deleted list still has a's edit
+        let hidden: String = b
+            .list(&list)
+            .unwrap()
+            .iter()
+            .map(|x| match x {
+                Value::Leaf(c) => *c,
+                Value::List => unreachable!(),
+            })
+            .collect();
+        assert_eq!(hidden, "x1y");
+    }
+
+    #[test]
+    fn concurrent() {
+        concurrent_edits::<Fugue>();
+        concurrent_edits::<Yata>();
+        concurrent_edits::<Woot>();
+        concurrent_edits::<Rga>();
+    }
+}
@@ -0,0 +1,336 @@
+//! A simulated network for the tests, unlike the perfect pairwise sync of the test actors.
+//!
+//! Every packet can be lost, duplicated or delayed, which also reorders them, and the nodes
+//! can be split into partitions that can't reach each other until they rejoin. The nodes buffer
+//! the ops whose dependencies haven't arrived, skip the ops they've seen, and recover the lost
+//! ones by gossiping everything they know. Everything is driven by one seeded rng, so a run
+//! can be reproduced from its seed.
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::test::{Pending, TestFramework};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// the probability that a packet is lost
+    pub loss: f64,
+    /// the probability that a packet is sent twice
+    pub duplicate: f64,
+    /// packets are delivered after `0..=max_delay` steps
+    pub max_delay: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            loss: 0.1,
+            duplicate: 0.1,
+            max_delay: 5,
+        }
+    }
+}
+
+enum Message<T: TestFramework> {
+    Op(T::OpUnit),
+    /// delete ops have no id, so they're identified by their sender and sequence number
+    Delete((usize, usize), T::DeleteOp),
+}
+
+impl<T: TestFramework> Clone for Message<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Message::Op(op) => Message::Op(op.clone()),
+            Message::Delete(id, op) => Message::Delete(*id, op.clone()),
+        }
+    }
+}
+
+struct Packet<T: TestFramework> {
+    to: usize,
+    deliver_at: usize,
+    message: Message<T>,
+}
+
+pub struct Node<T: TestFramework> {
+    pub container: T::Container,
+    /// the integrated ops
+    ops: Vec<T::OpUnit>,
+    /// the received delete ops, integrated or not
+    del_ops: Vec<((usize, usize), T::DeleteOp)>,
+    pending: Pending<T>,
+}
+
+impl<T: TestFramework> Node<T> {
+    fn new(idx: usize) -> Self {
+        Node {
+            container: T::new_container(idx),
+            ops: Vec::new(),
+            del_ops: Vec::new(),
+            pending: Pending::new(),
+        }
+    }
+
+    fn receive(&mut self, message: Message<T>) {
+        match message {
+            Message::Op(op) => {
+                if T::integrated(&self.container, T::id(&op)) {
+                    return;
+                }
+
+                self.pending.push(op);
+            }
+            Message::Delete(id, op) => {
+                if self.del_ops.iter().any(|(x, _)| *x == id) {
+                    return;
+                }
+
+                // a delete may arrive before the ops it deletes
+                self.pending.push_delete(op.clone());
+                self.del_ops.push((id, op));
+            }
+        }
+        self.ops.extend(self.pending.apply(&mut self.container));
+    }
+}
+
+pub struct Network<T: TestFramework> {
+    pub nodes: Vec<Node<T>>,
+    in_flight: Vec<Packet<T>>,
+    /// the partition of every node, packets between partitions are dropped
+    partitions: Vec<usize>,
+    config: Config,
+    time: usize,
+    rng: StdRng,
+}
+
+impl<T: TestFramework> Network<T> {
+    pub fn new(seed: u64, n_nodes: usize, config: Config) -> Self {
+        Network {
+            nodes: (0..n_nodes).map(Node::new).collect(),
+            in_flight: Vec::new(),
+            partitions: vec![0; n_nodes],
+            config,
+            time: 0,
+            rng: rand::SeedableRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Insert at `pos` on `node` and broadcast the op
+    pub fn insert(&mut self, node: usize, pos: usize) {
+        let op = T::new_op(&mut self.nodes[node].container, pos);
+        self.nodes[node].receive(Message::Op(op.clone()));
+        self.broadcast(node, Message::Op(op));
+    }
+
+    /// Delete `len` elements from `pos` on `node` and broadcast the delete
+    pub fn delete(&mut self, node: usize, pos: usize, len: usize) {
+        let op = T::new_del_op(&self.nodes[node].container, pos, len);
+        let id = (node, self.nodes[node].del_ops.len());
+        self.nodes[node].receive(Message::Delete(id, op.clone()));
+        self.broadcast(node, Message::Delete(id, op));
+    }
+
+    /// `from` sends everything it knows to `to`, which recovers the lost packets
+    pub fn gossip(&mut self, from: usize, to: usize) {
+        let node = &self.nodes[from];
+        let messages: Vec<Message<T>> = node
+            .ops
+            .iter()
+            .map(|op| Message::Op(op.clone()))
+            .chain(
+                node.del_ops
+                    .iter()
+                    .map(|(id, op)| Message::Delete(*id, op.clone())),
+            )
+            .collect();
+        for message in messages {
+            self.send(from, to, message);
+        }
+    }
+
+    /// Split the nodes into partitions, `partitions[i]` is the partition of node `i`
+    pub fn partition(&mut self, partitions: Vec<usize>) {
+        assert_eq!(partitions.len(), self.nodes.len());
+        self.partitions = partitions;
+    }
+
+    /// All the nodes can reach each other again
+    pub fn rejoin(&mut self) {
+        self.partitions = vec![0; self.nodes.len()];
+    }
+
+    /// Advance the time by one step and deliver the packets that are due, in random order
+    pub fn step(&mut self) {
+        self.time += 1;
+        let time = self.time;
+        let (mut due, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition(|x| x.deliver_at <= time);
+        self.in_flight = rest;
+        while !due.is_empty() {
+            let packet = due.swap_remove(self.rng.gen_range(0..due.len()));
+            self.nodes[packet.to].receive(packet.message);
+        }
+    }
+
+    /// Rejoin, then gossip over a reliable network until every packet is delivered
+    pub fn settle(&mut self) {
+        self.rejoin();
+        let loss = std::mem::replace(&mut self.config.loss, 0.);
+        for from in 0..self.nodes.len() {
+            for to in 0..self.nodes.len() {
+                if from != to {
+                    self.gossip(from, to);
+                }
+            }
+        }
+        while !self.in_flight.is_empty() {
+            self.step();
+        }
+        self.config.loss = loss;
+    }
+
+    /// # Panics
+    ///
+    /// If the nodes have different content or ops that still wait for their dependencies
+    pub fn check(&self) {
+        for node in self.nodes.iter() {
+            assert!(node.pending.is_empty(), "ops miss their dependencies");
+            if !T::is_content_eq(&self.nodes[0].container, &node.container) {
+                dbg!(&self.nodes[0].container);
+                dbg!(&node.container);
+                panic!("Nodes are not equal");
+            }
+        }
+    }
+
+    fn broadcast(&mut self, from: usize, message: Message<T>) {
+        for to in 0..self.nodes.len() {
+            if to != from {
+                self.send(from, to, message.clone());
+            }
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, message: Message<T>) {
+        if self.partitions[from] != self.partitions[to] || self.rng.gen_bool(self.config.loss) {
+            return;
+        }
+
+        let copies = if self.rng.gen_bool(self.config.duplicate) {
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let deliver_at = self.time + self.rng.gen_range(0..=self.config.max_delay);
+            self.in_flight.push(Packet {
+                to,
+                deliver_at,
+                message: message.clone(),
+            });
+        }
+    }
+
+    fn random_partitions(&mut self) -> Vec<usize> {
+        let n = self.nodes.len();
+        let n_partitions = self.rng.gen_range(2..=n);
+        (0..n)
+            .map(|_| self.rng.gen_range(0..n_partitions))
+            .collect()
+    }
+}
+
+/// Edit the nodes randomly while the network loses, duplicates and reorders the packets and
+/// splits and rejoins the nodes. The nodes must converge after the network settles.
+pub fn test_network<T: TestFramework>(seed: u64, n_nodes: usize, round: usize, config: Config) {
+    let mut network: Network<T> = Network::new(seed, n_nodes, config);
+    for _ in 0..round {
+        let node = network.rng.gen_range(0..n_nodes);
+        match network.rng.gen_range(0..20) {
+            0..=11 => {
+                let pos = network.rng.gen::<u8>() as usize;
+                network.insert(node, pos);
+            }
+            12..=14 => {
+                let pos = network.rng.gen_range(0..16);
+                let len = network.rng.gen_range(1..4);
+                network.delete(node, pos, len);
+            }
+            15 => {
+                let to = (node + network.rng.gen_range(1..n_nodes)) % n_nodes;
+                network.gossip(node, to);
+            }
+            16 => {
+                let partitions = network.random_partitions();
+                network.partition(partitions);
+            }
+            17 => network.rejoin(),
+            _ => network.step(),
+        }
+    }
+
+    network.settle();
+    network.check();
+}
+
+#[cfg(test)]
+mod network_test {
+    use super::*;
+    use crate::fugue_dumb_impl::FugueImpl;
+
+    #[test]
+    fn partitions_rejoin() {
+        let config = Config {
+            loss: 0.,
+            duplicate: 0.5,
+            max_delay: 3,
+        };
+        let mut network: Network<FugueImpl> = Network::new(0, 3, config);
+        network.partition(vec![0, 0, 1]);
+        network.insert(0, 0);
+        network.insert(2, 0);
+        for _ in 0..10 {
+            network.step();
+        }
+        assert_eq!(network.nodes[1].ops.len(), 1);
+        assert_eq!(network.nodes[2].ops.len(), 1);
+        assert!(!<FugueImpl>::is_content_eq(
+            &network.nodes[0].container,
+            &network.nodes[2].container
+        ));
+
+        network.rejoin();
+        network.gossip(2, 0);
+        network.gossip(0, 2);
+        while network.in_flight() > 0 {
+            network.step();
+        }
+        // 0 knows the op of 2 now
+        network.gossip(0, 1);
+        while network.in_flight() > 0 {
+            network.step();
+        }
+        network.check();
+        assert_eq!(network.nodes[1].ops.len(), 2);
+    }
+
+    #[test]
+    fn runs_are_reproducible() {
+        let run = |seed| {
+            let mut network: Network<FugueImpl> = Network::new(seed, 3, Default::default());
+            for i in 0..50 {
+                network.insert(i % 3, i * 7);
+                network.step();
+            }
+            network.settle();
+            network.check();
+            network.nodes.remove(0).container
+        };
+        assert!(<FugueImpl>::is_content_eq(&run(1), &run(1)));
+    }
+}
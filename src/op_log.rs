@@ -0,0 +1,172 @@
+//! Storage for ops, indexed by their ids.
+//!
+//! The ops of every client are kept in runs of consecutive clocks, so an op is found with
+//! two binary searches. The log also remembers the order the ops were pushed in, as runs
+//! of clock ranges. Ops are pushed after their dependencies, so that order is causal.
+
+use std::{collections::BTreeMap, fmt::Debug, ops::Range};
+
+pub trait LogOp {
+    type Client: Ord + Clone + Debug;
+    fn client(&self) -> Self::Client;
+    fn clock(&self) -> usize;
+}
+
+#[derive(Debug, Clone)]
+pub struct OpLog<T: LogOp> {
+    /// the runs of every client, by their first clock
+    runs: BTreeMap<T::Client, BTreeMap<usize, Vec<T>>>,
+    /// the clock ranges in the order they were pushed
+    order: Vec<(T::Client, Range<usize>)>,
+    len: usize,
+}
+
+impl<T: LogOp> Default for OpLog<T> {
+    fn default() -> Self {
+        OpLog {
+            runs: BTreeMap::new(),
+            order: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T: LogOp> OpLog<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// # Panics
+    ///
+    /// If the log has an op of the same client with a greater or equal clock
+    pub fn push(&mut self, op: T) {
+        let client = op.client();
+        let clock = op.clock();
+        assert!(
+            clock >= self.end(&client),
+            "ops of a client must be pushed in clock order"
+        );
+        let runs = self.runs.entry(client.clone()).or_default();
+        match runs.iter_mut().next_back() {
+            Some((start, run)) if start + run.len() == clock => run.push(op),
+            _ => {
+                runs.insert(clock, vec![op]);
+            }
+        }
+
+        match self.order.last_mut() {
+            Some((last, range)) if *last == client && range.end == clock => range.end += 1,
+            _ => self.order.push((client, clock..clock + 1)),
+        }
+        self.len += 1;
+    }
+
+    pub fn get(&self, client: &T::Client, clock: usize) -> Option<&T> {
+        let (start, run) = self.runs.get(client)?.range(..=clock).next_back()?;
+        run.get(clock - start)
+    }
+
+    pub fn contains(&self, client: &T::Client, clock: usize) -> bool {
+        self.get(client, clock).is_some()
+    }
+
+    /// The exclusive end of the clocks of `client`
+    pub fn end(&self, client: &T::Client) -> usize {
+        self.runs
+            .get(client)
+            .and_then(|runs| runs.iter().next_back())
+            .map(|(start, run)| start + run.len())
+            .unwrap_or(0)
+    }
+
+    /// All the ops in the order they were pushed
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.iter_since(|_| 0)
+    }
+
+    /// The ops in the order they were pushed, skipping the clocks of every client
+    /// before `end_of(client)`, e.g. the ops a version vector doesn't include
+    pub fn iter_since(&self, end_of: impl Fn(&T::Client) -> usize) -> impl Iterator<Item = &T> {
+        self.order.iter().flat_map(move |(client, range)| {
+            let start = std::cmp::max(range.start, end_of(client));
+            self.slice(client, start..std::cmp::max(start, range.end))
+        })
+    }
+
+    /// `range` must be inside one run
+    fn slice(&self, client: &T::Client, range: Range<usize>) -> &[T] {
+        if range.is_empty() {
+            return &[];
+        }
+
+        let (start, run) = self.runs[client].range(..=range.start).next_back().unwrap();
+        &run[range.start - start..range.end - start]
+    }
+}
+
+#[cfg(test)]
+mod op_log_test {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Id(char, usize);
+
+    impl LogOp for Id {
+        type Client = char;
+
+        fn client(&self) -> char {
+            self.0
+        }
+
+        fn clock(&self) -> usize {
+            self.1
+        }
+    }
+
+    #[test]
+    fn lookup_and_order() {
+        let mut log = OpLog::new();
+        let pushed = [
+            Id('a', 0),
+            Id('a', 1),
+            Id('b', 0),
+            Id('a', 2),
+            Id('b', 1),
+            Id('b', 2),
+            Id('a', 5),
+        ];
+        for id in pushed {
+            log.push(id);
+        }
+
+        assert_eq!(log.len(), 7);
+        assert_eq!(log.get(&'a', 2), Some(&Id('a', 2)));
+        assert_eq!(log.get(&'a', 5), Some(&Id('a', 5)));
+        assert!(!log.contains(&'a', 3));
+        assert!(!log.contains(&'c', 0));
+        assert_eq!(log.end(&'a'), 6);
+        assert_eq!(log.iter().copied().collect::<Vec<_>>(), pushed.to_vec());
+
+        let since: Vec<_> = log
+            .iter_since(|x| if *x == 'a' { 2 } else { 1 })
+            .copied()
+            .collect();
+        assert_eq!(since, vec![Id('a', 2), Id('b', 1), Id('b', 2), Id('a', 5)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn clocks_must_increase() {
+        let mut log = OpLog::new();
+        log.push(Id('a', 1));
+        log.push(Id('a', 0));
+    }
+}
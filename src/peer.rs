@@ -0,0 +1,226 @@
+//! Compact indices for arbitrary peer ids.
+//!
+//! Peers usually pick random 64/128-bit ids or names, which can't index a version vector.
+//! A [PeerRegistry] gives every peer of a document a small index in the order it's seen.
+//! The indices are local to the registry, so anything sent to other replicas should
+//! carry the peer ids instead, see [PeerRegistry::encode_version] and [Version].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// Peers pick random 128-bit ids, so they don't need to agree on them
+pub type PeerId = u128;
+
+#[derive(Debug, Clone)]
+pub struct PeerRegistry<P> {
+    peers: Vec<P>,
+    indices: HashMap<P, usize>,
+}
+
+impl<P> Default for PeerRegistry<P> {
+    fn default() -> Self {
+        PeerRegistry {
+            peers: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+}
+
+impl<P: Clone + Eq + Hash> PeerRegistry<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The index of `peer`, it's assigned on the first call
+    pub fn register(&mut self, peer: &P) -> usize {
+        if let Some(&index) = self.indices.get(peer) {
+            return index;
+        }
+
+        self.peers.push(peer.clone());
+        self.indices.insert(peer.clone(), self.peers.len() - 1);
+        self.peers.len() - 1
+    }
+
+    pub fn index_of(&self, peer: &P) -> Option<usize> {
+        self.indices.get(peer).copied()
+    }
+
+    pub fn peer(&self, index: usize) -> Option<&P> {
+        self.peers.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// The peers in the order of their indices
+    pub fn iter(&self) -> impl Iterator<Item = &P> {
+        self.peers.iter()
+    }
+
+    /// Replace the indices of a version vector with the peer ids, skipping the zeros
+    pub fn encode_version(&self, version: &[usize]) -> Vec<(P, usize)> {
+        version
+            .iter()
+            .enumerate()
+            .filter(|(_, &end)| end > 0)
+            .map(|(index, &end)| (self.peers[index].clone(), end))
+            .collect()
+    }
+
+    /// The inverse of [PeerRegistry::encode_version], the unknown peers are registered
+    pub fn decode_version(&mut self, encoded: &[(P, usize)]) -> Vec<usize> {
+        let mut version = Vec::new();
+        for (peer, end) in encoded {
+            let index = self.register(peer);
+            if version.len() <= index {
+                version.resize(index + 1, 0);
+            }
+            version[index] = *end;
+        }
+
+        version
+    }
+}
+
+/// A version vector keyed by peer ids, which is what replicas exchange.
+/// The peers that are missing have no ops.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version<P = PeerId> {
+    /// exclusive end of the clocks of every peer, never 0
+    ends: BTreeMap<P, usize>,
+}
+
+impl<P> Default for Version<P> {
+    fn default() -> Self {
+        Version {
+            ends: BTreeMap::new(),
+        }
+    }
+}
+
+impl<P: Clone + Ord> Version<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The exclusive end of the clocks of `peer`
+    pub fn end(&self, peer: &P) -> usize {
+        self.ends.get(peer).copied().unwrap_or(0)
+    }
+
+    /// Whether the op `clock` of `peer` is included
+    pub fn includes(&self, peer: &P, clock: usize) -> bool {
+        clock < self.end(peer)
+    }
+
+    /// Include the op `clock` of `peer` and the ones before it
+    pub fn include(&mut self, peer: &P, clock: usize) {
+        let end = clock.saturating_add(1);
+        if self.end(peer) < end {
+            self.ends.insert(peer.clone(), end);
+        }
+    }
+
+    /// The ops that either version includes
+    pub fn join(&self, other: &Self) -> Self {
+        let mut ans = self.clone();
+        for (peer, end) in other.iter() {
+            ans.include(peer, end - 1);
+        }
+
+        ans
+    }
+
+    /// Whether `self` includes every op of `other`
+    pub fn dominates(&self, other: &Self) -> bool {
+        other.iter().all(|(peer, end)| self.end(peer) >= end)
+    }
+
+    /// The number of peers with ops
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// The peers with ops and their ends, by peer id
+    pub fn iter(&self) -> impl Iterator<Item = (&P, usize)> {
+        self.ends.iter().map(|(peer, &end)| (peer, end))
+    }
+}
+
+impl<P: Clone + Ord> FromIterator<(P, usize)> for Version<P> {
+    /// The pairs of peers and ends, the zero ends are skipped
+    fn from_iter<T: IntoIterator<Item = (P, usize)>>(iter: T) -> Self {
+        let mut ans = Version::new();
+        for (peer, end) in iter {
+            if end > 0 {
+                ans.include(&peer, end - 1);
+            }
+        }
+
+        ans
+    }
+}
+
+#[cfg(test)]
+mod peer_test {
+    use super::*;
+
+    #[test]
+    fn version_vectors_survive_different_indices() {
+        let mut a: PeerRegistry<u128> = PeerRegistry::new();
+        let mut b: PeerRegistry<u128> = PeerRegistry::new();
+        let (x, y, z) = (u128::MAX - 7, 1 << 100, 42);
+        assert_eq!(a.register(&x), 0);
+        assert_eq!(a.register(&y), 1);
+        assert_eq!(a.register(&x), 0);
+        assert_eq!(b.register(&z), 0);
+
+        let encoded = a.encode_version(&[3, 5]);
+        let version = b.decode_version(&encoded);
+        assert_eq!(b.index_of(&x), Some(1));
+        assert_eq!(b.index_of(&y), Some(2));
+        assert_eq!(version, vec![0, 3, 5]);
+        assert_eq!(b.encode_version(&version), encoded);
+        assert_eq!(b.peer(0), Some(&z));
+    }
+
+    #[test]
+    fn versions() {
+        let (x, y) = (u128::MAX, 1 << 100);
+        let a: Version = [(x, 3), (y, 0)].into_iter().collect();
+        let b: Version = [(y, 2), (x, 1)].into_iter().collect();
+        assert_eq!(a.len(), 1);
+        assert!(a.includes(&x, 2) && !a.includes(&x, 3) && !a.includes(&y, 0));
+        let joined = a.join(&b);
+        assert_eq!(joined.iter().collect::<Vec<_>>(), vec![(&y, 2), (&x, 3)]);
+        assert!(joined.dominates(&a) && joined.dominates(&b) && !a.dominates(&b));
+
+        let mut registry = PeerRegistry::new();
+        let version =
+            registry.decode_version(&joined.iter().map(|(&p, e)| (p, e)).collect::<Vec<_>>());
+        let encoded: Version = registry.encode_version(&version).into_iter().collect();
+        assert_eq!(encoded, joined);
+    }
+
+    #[test]
+    fn string_ids() {
+        let mut registry: PeerRegistry<String> = PeerRegistry::new();
+        registry.register(&"alice".to_string());
+        registry.register(&"bob".to_string());
+        let names: Vec<_> = registry.iter().map(|x| x.as_str()).collect();
+        assert_eq!(names, vec!["alice", "bob"]);
+        assert_eq!(registry.len(), 2);
+    }
+}
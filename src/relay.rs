@@ -0,0 +1,458 @@
+//! A relay server that stores and forwards the ops of [ListDoc]s without integrating them.
+//!
+//! The relay never orders the elements. It keeps the ops in an [OpLog], the [Version],
+//! the ids of the inserts and the deleted ids, which is enough to check that every op comes
+//! after its dependencies and to answer [Relay::ops_since]. The list is only built when
+//! [Relay::materialize] is called, with whatever algorithm the caller picks.
+//!
+//! The relay speaks the protocol of [crate::sync], so [crate::sync::Session]s can connect to it.
+//! It keeps a [Connection] for every peer instead of a session.
+
+use std::{fmt::Debug, fmt::Display};
+
+use crate::{
+    id_span::IdSpanSet,
+    list_doc::{includes, Algorithm, ListDoc, ListOp, OpId},
+    op_log::OpLog,
+    peer::{PeerId, Version},
+    sync::Message,
+    tie_break::TieBreak,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayError {
+    /// the previous ops of the client are missing
+    Gap { id: OpId, expected: usize },
+    /// an origin or a delete target is missing
+    MissingDependency { id: OpId, dependency: OpId },
+    /// an origin or a delete target is a delete op
+    NotAnInsert { id: OpId, target: OpId },
+    /// the op is the first of a new client, but the relay has the most peers it accepts
+    TooManyPeers { id: OpId, max: usize },
+    /// a message other than [Message::Hello] before the handshake
+    NotConnected,
+}
+
+impl Display for RelayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RelayError::Gap { id, expected } => {
+                write!(f, "op {:?} should have clock {}", id, expected)
+            }
+            RelayError::MissingDependency { id, dependency } => {
+                write!(f, "op {:?} depends on the missing op {:?}", id, dependency)
+            }
+            RelayError::NotAnInsert { id, target } => {
+                write!(f, "op {:?} refers to the delete op {:?}", id, target)
+            }
+            RelayError::TooManyPeers { id, max } => {
+                write!(
+                    f,
+                    "op {:?} is from a new client, over the limit of {}",
+                    id, max
+                )
+            }
+            RelayError::NotConnected => write!(f, "message before the handshake"),
+        }
+    }
+}
+
+impl std::error::Error for RelayError {}
+
+/// The state of the relay's side of a connection to one peer
+#[derive(Debug, Clone, Default)]
+pub struct Connection {
+    connected: bool,
+    /// the ops the peer has or that are sent to it
+    sent: Version,
+}
+
+impl Connection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The connection is lost, the peer must send [Message::Hello] again
+    pub fn disconnect(&mut self) {
+        self.connected = false;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Relay<V> {
+    log: OpLog<ListOp<V>>,
+    version: Version,
+    /// the ids of the insert ops, the others are delete ops
+    inserts: IdSpanSet<PeerId>,
+    deleted: IdSpanSet<PeerId>,
+    max_peers: usize,
+}
+
+impl<V> Default for Relay<V> {
+    fn default() -> Self {
+        Relay {
+            log: OpLog::new(),
+            version: Version::new(),
+            inserts: IdSpanSet::new(),
+            deleted: IdSpanSet::new(),
+            max_peers: usize::MAX,
+        }
+    }
+}
+
+impl<V: Clone + Debug + 'static> Relay<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A relay that rejects the ops of new clients once it has the ops of `max_peers`
+    /// clients, since every client costs some memory
+    pub fn with_max_peers(max_peers: usize) -> Self {
+        Relay {
+            max_peers,
+            ..Self::default()
+        }
+    }
+
+    /// The number of ops
+    pub fn len(&self) -> usize {
+        self.log.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.log.is_empty()
+    }
+
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    /// The ids of the deleted elements
+    pub fn deleted(&self) -> &IdSpanSet<PeerId> {
+        &self.deleted
+    }
+
+    pub fn contains(&self, id: OpId) -> bool {
+        includes(&self.version, id)
+    }
+
+    /// Store `op` after checking its dependencies. Unlike [ListDoc::apply_remote], the ops must
+    /// come in causal order, like they do over a [crate::sync] connection. It returns whether
+    /// the op is new, the ops that are already stored are ignored.
+    pub fn apply(&mut self, op: ListOp<V>) -> Result<bool, RelayError> {
+        let id = op.id();
+        if self.contains(id) {
+            return Ok(false);
+        }
+
+        let expected = self.version.end(&id.client_id);
+        if id.clock != expected {
+            return Err(RelayError::Gap { id, expected });
+        }
+        if expected == 0 && self.version.len() >= self.max_peers {
+            return Err(RelayError::TooManyPeers {
+                id,
+                max: self.max_peers,
+            });
+        }
+
+        let targets: IdSpanSet<PeerId> = match &op {
+            ListOp::Insert(op) => {
+                let origins = [op.left, op.right].into_iter().flatten();
+                if let Some(dependency) = origins.clone().find(|x| !self.contains(*x)) {
+                    return Err(RelayError::MissingDependency { id, dependency });
+                }
+                origins.map(|x| (x.client_id, x.clock)).collect()
+            }
+            ListOp::Delete(op) => {
+                // by span, since a hostile one may be huge
+                for (&client_id, span) in op.targets.iter() {
+                    let end = self.version.end(&client_id);
+                    if end < span.end {
+                        let clock = std::cmp::max(span.start, end);
+                        let dependency = OpId { client_id, clock };
+                        return Err(RelayError::MissingDependency { id, dependency });
+                    }
+                }
+                op.targets.clone()
+            }
+        };
+        if let Some((&client_id, clock)) = targets.difference(&self.inserts).ids().next() {
+            let target = OpId { client_id, clock };
+            return Err(RelayError::NotAnInsert { id, target });
+        }
+
+        match &op {
+            ListOp::Insert(_) => self.inserts.insert(id.client_id, id.clock),
+            ListOp::Delete(op) => self.deleted.extend(&op.targets),
+        }
+        self.version.include(&id.client_id, id.clock);
+        self.log.push(op);
+        Ok(true)
+    }
+
+    /// The ops that `version` doesn't include, in causal order
+    pub fn ops_since(&self, version: &Version) -> Vec<ListOp<V>> {
+        self.log
+            .iter_since(|client| version.end(client))
+            .cloned()
+            .collect()
+    }
+
+    /// Integrate all the ops into a new doc of `client_id` with the algorithm `A`
+    pub fn materialize<A: Algorithm, P: TieBreak<PeerId> + 'static>(
+        &self,
+        client_id: PeerId,
+    ) -> ListDoc<A, V, P> {
+        let mut doc = ListDoc::new(client_id);
+        for op in self.log.iter() {
+            doc.apply_remote(op.clone());
+        }
+
+        doc
+    }
+
+    /// Handle a message of the peer of `connection`, it returns the replies.
+    /// The ops of a [Message::Ops] before an invalid one are kept.
+    pub fn receive(
+        &mut self,
+        connection: &mut Connection,
+        message: Message<V>,
+    ) -> Result<Vec<Message<V>>, RelayError> {
+        match message {
+            Message::Hello { version } => {
+                connection.sent = version;
+                connection.connected = true;
+                Ok(vec![Message::Hello {
+                    version: self.version.clone(),
+                }])
+            }
+            _ if !connection.connected => Err(RelayError::NotConnected),
+            Message::Ops { ops } => {
+                for op in ops {
+                    let id = op.id();
+                    self.apply(op)?;
+                    // the peer has it, so it's not echoed back
+                    connection.sent.include(&id.client_id, id.clock);
+                }
+                Ok(vec![Message::Ack {
+                    version: self.version.clone(),
+                }])
+            }
+            Message::Ack { version } => {
+                connection.sent = connection.sent.join(&version);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// The ops the peer of `connection` misses, call it after other peers send ops
+    pub fn poll(&self, connection: &mut Connection) -> Option<Message<V>> {
+        if !connection.connected || connection.sent.dominates(&self.version) {
+            return None;
+        }
+
+        let ops = self.ops_since(&connection.sent);
+        connection.sent = connection.sent.join(&self.version);
+        Some(Message::Ops { ops })
+    }
+}
+
+#[cfg(test)]
+mod relay_test {
+    use super::*;
+    use crate::{
+        list_doc::{DeleteOp, Fugue, InsertOp, Yata},
+        sync::Session,
+        tie_break::Ascending,
+    };
+
+    struct Client {
+        doc: ListDoc<Fugue, char>,
+        session: Session<char>,
+        connection: Connection,
+    }
+
+    impl Client {
+        fn new(client_id: PeerId) -> Self {
+            let doc = ListDoc::new(client_id);
+            let mut session = Session::new();
+            session.connect(&doc);
+            Client {
+                doc,
+                session,
+                connection: Connection::new(),
+            }
+        }
+    }
+
+    /// Exchange messages between the relay and the clients until it's quiet
+    fn run(relay: &mut Relay<char>, clients: &mut [Client]) {
+        loop {
+            let mut busy = false;
+            for client in clients.iter_mut() {
+                while let Some(message) = client.session.poll(&client.doc) {
+                    busy = true;
+                    for reply in relay.receive(&mut client.connection, message).unwrap() {
+                        client.session.receive(&mut client.doc, reply).unwrap();
+                    }
+                }
+            }
+            for client in clients.iter_mut() {
+                while let Some(message) = relay.poll(&mut client.connection) {
+                    busy = true;
+                    client.session.receive(&mut client.doc, message).unwrap();
+                }
+            }
+            if !busy {
+                break;
+            }
+        }
+    }
+
+    fn content<A: Algorithm>(doc: &ListDoc<A, char>) -> String {
+        doc.iter().collect()
+    }
+
+    #[test]
+    fn clients_sync_through_the_relay() {
+        let mut relay = Relay::new();
+        let mut clients: Vec<Client> = (0..3).map(Client::new).collect();
+        clients[0].doc.insert(0, "hello".chars());
+        clients[1].doc.insert(0, "world".chars());
+        run(&mut relay, &mut clients);
+        clients[2].doc.delete(0..2);
+        clients[0].doc.insert(3, "!".chars());
+        run(&mut relay, &mut clients);
+
+        let expected = content(&clients[0].doc);
+        assert_eq!(expected.len(), 9);
+        for client in clients.iter() {
+            assert_eq!(content(&client.doc), expected);
+            assert!(client.session.is_synced(&client.doc));
+        }
+        assert_eq!(relay.len(), 12);
+        assert_eq!(relay.deleted().len(), 2);
+        let fugue: ListDoc<Fugue, char> = relay.materialize::<Fugue, Ascending>(9);
+        assert_eq!(content(&fugue), expected);
+        // another algorithm may order the concurrent inserts differently
+        let yata: ListDoc<Yata, char> = relay.materialize::<Yata, Ascending>(9);
+        assert_eq!(yata.len(), 9);
+
+        // a client that has everything gets nothing
+        let mut late = Client::new(3);
+        late.doc.merge(&clients[1].doc);
+        late.session.connect(&late.doc);
+        let hello = late.session.poll(&late.doc).unwrap();
+        relay.receive(&mut late.connection, hello).unwrap();
+        assert_eq!(relay.poll(&mut late.connection), None);
+    }
+
+    #[test]
+    fn invalid_ops() {
+        let mut relay = Relay::new();
+        let mut doc = ListDoc::<Fugue, char>::new(0);
+        let ops = doc.insert(0, "ab".chars());
+        assert_eq!(
+            relay.apply(ops[1].clone()),
+            Err(RelayError::Gap {
+                id: ops[1].id(),
+                expected: 0
+            })
+        );
+        assert_eq!(relay.apply(ops[0].clone()), Ok(true));
+        assert_eq!(relay.apply(ops[0].clone()), Ok(false));
+        let delete = doc.delete(0..1).remove(0);
+
+        let id = |client_id, clock| OpId { client_id, clock };
+        let dangling = ListOp::Insert(InsertOp {
+            id: id(1, 0),
+            lamport: 5,
+            left: Some(id(0, 1)),
+            right: None,
+            value: 'x',
+        });
+        assert_eq!(
+            relay.apply(dangling),
+            Err(RelayError::MissingDependency {
+                id: id(1, 0),
+                dependency: id(0, 1)
+            })
+        );
+
+        relay.apply(ops[1].clone()).unwrap();
+        relay.apply(delete.clone()).unwrap();
+        let bogus = ListOp::Delete(DeleteOp {
+            id: id(1, 0),
+            lamport: 5,
+            targets: [(0, 2)].into_iter().collect(),
+        });
+        assert_eq!(
+            relay.apply(bogus),
+            Err(RelayError::NotAnInsert {
+                id: id(1, 0),
+                target: delete.id()
+            })
+        );
+        assert_eq!(relay.version(), &[(0, 3)].into_iter().collect());
+        assert_eq!(
+            relay.ops_since(&[(0, 1)].into_iter().collect()),
+            vec![ops[1].clone(), delete]
+        );
+
+        // huge client ids and clocks
+        let hostile = |client_id, clock, targets: IdSpanSet<PeerId>| {
+            ListOp::<char>::Delete(DeleteOp {
+                id: id(client_id, clock),
+                lamport: 5,
+                targets,
+            })
+        };
+        assert_eq!(
+            relay.apply(hostile(u128::MAX, usize::MAX, IdSpanSet::new())),
+            Err(RelayError::Gap {
+                id: id(u128::MAX, usize::MAX),
+                expected: 0
+            })
+        );
+        assert_eq!(
+            relay.apply(hostile(u128::MAX, 0, IdSpanSet::new())),
+            Ok(true)
+        );
+        let mut huge = IdSpanSet::new();
+        huge.insert_span(0, 0..usize::MAX);
+        assert_eq!(
+            relay.apply(hostile(1 << 100, 0, huge)),
+            Err(RelayError::MissingDependency {
+                id: id(1 << 100, 0),
+                dependency: id(0, 3)
+            })
+        );
+        assert_eq!(
+            relay.version(),
+            &[(0, 3), (u128::MAX, 1)].into_iter().collect()
+        );
+
+        let mut small = Relay::with_max_peers(1);
+        small.apply(ops[0].clone()).unwrap();
+        assert_eq!(
+            small.apply(hostile(u128::MAX, 0, IdSpanSet::new())),
+            Err(RelayError::TooManyPeers {
+                id: id(u128::MAX, 0),
+                max: 1
+            })
+        );
+        assert_eq!(small.apply(ops[1].clone()), Ok(true));
+
+        let mut connection = Connection::new();
+        assert_eq!(
+            relay.receive(
+                &mut connection,
+                Message::Ack {
+                    version: Version::new()
+                }
+            ),
+            Err(RelayError::NotConnected)
+        );
+    }
+}
@@ -1,28 +1,44 @@
+use std::cmp::Ordering;
+
 use crate::crdt::{GetOp, ListCrdt};
 
 pub trait Rga: ListCrdt {
-    type Lamport: Ord;
+    #[deprecated(note = "concurrent inserts are ordered by `ListCrdt::cmp_id` now")]
     type ClientId: Ord;
-    fn left(op: &Self::OpUnit) -> Option<Self::OpId>;
+    type Lamport: Ord;
+    #[deprecated(note = "concurrent inserts are ordered by `ListCrdt::cmp_id` now")]
+    #[allow(deprecated)]
     fn client_id(id: Self::OpId) -> Self::ClientId;
+    fn left(op: &Self::OpUnit) -> Option<Self::OpId>;
     fn lamport(op: &Self::OpUnit) -> Self::Lamport;
     fn len(container: &Self::Container) -> usize;
     fn insert_after(container: &mut Self::Container, left: Option<Self::OpId>, op: Self::OpUnit);
 }
 
 pub fn integrate<T: Rga>(container: &mut T::Container, to_insert: T::OpUnit) {
+    if T::integrated(container, T::id(&to_insert)) {
+        return;
+    }
+
     let origin_left = T::left(&to_insert);
-    let client_id = T::client_id(T::id(&to_insert));
     let lamport = T::lamport(&to_insert);
-    let cmp = (lamport, client_id);
-    let mut left = None;
+    let mut left = origin_left;
     for op in T::iter(container, origin_left, None) {
-        let op = op.get_op();
-        let op_client = T::client_id(T::id(&op));
-        if cmp < (T::lamport(&op), op_client) {
+        let op = op.get_op_ref();
+        if origin_left.is_some() && T::contains(op, origin_left.unwrap()) {
+            continue;
+        }
+
+        // Skip the concurrent ops inserted after the same origin with greater timestamps,
+        // their descendants have even greater timestamps. Equal lamports are broken by
+        // [ListCrdt::cmp_id].
+        let order = T::lamport(op)
+            .cmp(&lamport)
+            .then_with(|| T::cmp_id(op, &to_insert));
+        if order == Ordering::Less {
             break;
         }
-        left = Some(T::id(&op));
+        left = Some(T::id(op));
     }
 
     T::insert_after(container, left, to_insert);
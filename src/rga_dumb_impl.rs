@@ -1,34 +1,23 @@
-use std::{
-    collections::HashSet,
-    ops::{Deref, DerefMut},
-};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
 
-pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl};
-use crate::{crdt::ListCrdt, rga, test::TestFramework};
-use rand::Rng;
+use crate::dumb_common::peer_id;
+pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl, PeerId};
+use crate::id_span::IdSpanSet;
+use crate::tie_break::{Ascending, TieBreak};
+use crate::{crdt::ListCrdt, local::Tombstones, rga, test::TestFramework};
 
-pub struct RgaImpl;
-impl RgaImpl {
+pub struct RgaImpl<P = Ascending>(PhantomData<P>);
+impl<P: TieBreak<PeerId>> RgaImpl<P> {
     fn container_contains(
         container: &<Self as ListCrdt>::Container,
         op_id: Option<<Self as ListCrdt>::OpId>,
     ) -> bool {
-        if op_id.is_none() {
-            return true;
-        }
-
-        let op_id = op_id.unwrap();
-        container.content.iter().any(|x| x.id == op_id)
-
-        // if container.version_vector.len() <= op_id.client_id {
-        //     return false;
-        // }
-
-        // container.version_vector[op_id.client_id] > op_id.clock
+        container.contains(op_id)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RgaContainer {
     container: Container,
     next_lamport: u32,
@@ -48,13 +37,15 @@ impl DerefMut for RgaContainer {
     }
 }
 
-impl ListCrdt for RgaImpl {
+impl<P: TieBreak<PeerId>> ListCrdt for RgaImpl<P> {
     type OpUnit = Op;
 
     type OpId = OpId;
 
     type Container = RgaContainer;
 
+    type Pos = usize;
+
     type Cursor<'a> = Cursor<'a>;
 
     type Set = OpSetImpl;
@@ -62,12 +53,12 @@ impl ListCrdt for RgaImpl {
     type Iterator<'a> = Iter<'a>;
 
     fn iter(
-        container: &mut Self::Container,
+        container: &Self::Container,
         from: Option<Self::OpId>,
         to: Option<Self::OpId>,
     ) -> Self::Iterator<'_> {
         Iter {
-            arr: &mut container.content,
+            arr: &container.content,
             index: 0,
             start: from,
             end: to,
@@ -82,18 +73,34 @@ impl ListCrdt for RgaImpl {
     }
 
     fn cmp_id(op_a: &Self::OpUnit, op_b: &Self::OpUnit) -> std::cmp::Ordering {
-        op_a.id
-            .client_id
-            .cmp(&op_b.id.client_id)
+        P::cmp_client(&op_a.id.client_id, &op_b.id.client_id)
             .then(op_a.id.clock.cmp(&op_b.id.clock))
     }
 
     fn contains(op: &Self::OpUnit, id: Self::OpId) -> bool {
         op.id == id
     }
+
+    fn integrated(container: &Self::Container, id: Self::OpId) -> bool {
+        container.contains(Some(id))
+    }
+}
+
+impl<P: TieBreak<PeerId>> Tombstones for RgaImpl<P> {
+    fn get(container: &Self::Container, id: OpId) -> Option<&Op> {
+        container.content.get(id)
+    }
+
+    fn visible_id(container: &Self::Container, index: usize) -> Option<OpId> {
+        container.content.visible_id(index)
+    }
+
+    fn next_id(container: &Self::Container, id: Option<OpId>) -> Option<OpId> {
+        container.content.next_id(id)
+    }
 }
 
-impl rga::Rga for RgaImpl {
+impl<P: TieBreak<PeerId>> rga::Rga for RgaImpl<P> {
     fn len(container: &Self::Container) -> usize {
         container.content.len()
     }
@@ -102,8 +109,13 @@ impl rga::Rga for RgaImpl {
         op.left
     }
 
+    type ClientId = PeerId;
     type Lamport = u32;
 
+    fn client_id(id: Self::OpId) -> PeerId {
+        id.client_id
+    }
+
     fn lamport(op: &Self::OpUnit) -> Self::Lamport {
         op.lamport
     }
@@ -111,7 +123,7 @@ impl rga::Rga for RgaImpl {
     fn insert_after(container: &mut Self::Container, left: Option<Self::OpId>, op: Self::OpUnit) {
         match left {
             Some(left) => {
-                let pos = container.content.iter().position(|x| x.id == left).unwrap();
+                let pos = container.content.pos_of(left).unwrap();
                 container.content.insert(pos + 1, op);
             }
             None => {
@@ -119,26 +131,23 @@ impl rga::Rga for RgaImpl {
             }
         }
     }
-
-    type ClientId = usize;
-
-    fn client_id(id: Self::OpId) -> Self::ClientId {
-        id.client_id
-    }
 }
 
-impl TestFramework for RgaImpl {
+impl<P: TieBreak<PeerId>> TestFramework for RgaImpl<P> {
     fn is_content_eq(a: &Self::Container, b: &Self::Container) -> bool {
         a.content.eq(&b.content)
     }
 
+    fn fork(container: &Self::Container, id: usize) -> Self::Container {
+        RgaContainer {
+            container: container.container.fork(peer_id(id)),
+            next_lamport: container.next_lamport,
+        }
+    }
+
     fn new_container(id: usize) -> Self::Container {
         RgaContainer {
-            container: Container {
-                id,
-                version_vector: vec![0; 10],
-                ..Default::default()
-            },
+            container: Container::new(peer_id(id)),
             next_lamport: 0,
         }
     }
@@ -165,6 +174,7 @@ impl TestFramework for RgaImpl {
             },
             left,
             right,
+            degree: 0,
             deleted: false,
             lamport: container.next_lamport,
         };
@@ -174,42 +184,41 @@ impl TestFramework for RgaImpl {
         ans
     }
 
-    type DeleteOp = HashSet<Self::OpId>;
+    type DeleteOp = IdSpanSet<PeerId>;
 
     fn new_del_op(container: &Self::Container, mut pos: usize, mut len: usize) -> Self::DeleteOp {
         let content_len = container.content.real_len();
-        let mut deleted = HashSet::new();
+        let mut deleted = IdSpanSet::new();
         if content_len == 0 {
             return deleted;
         }
 
         pos %= content_len;
         len = std::cmp::min(len, content_len - pos);
-        for op in container.content.iter_real().skip(pos).take(len) {
-            deleted.insert(op.id);
+        for op in container.content.iter_real_from(pos).take(len) {
+            deleted.insert(op.id.client_id, op.id.clock);
         }
 
         deleted
     }
 
     fn integrate_delete_op(container: &mut Self::Container, delete_set: Self::DeleteOp) {
-        for op in container.content.iter_real_mut() {
-            if delete_set.contains(&op.id) {
-                op.deleted = true;
-            }
-        }
+        container.content.delete(&delete_set);
+        container.deleted = container.deleted.union(&delete_set);
+    }
+
+    fn can_integrate_delete_op(container: &Self::Container, op: &Self::DeleteOp) -> bool {
+        container.contains_all(op)
     }
 
     fn integrate(container: &mut Self::Container, op: Self::OpUnit) {
-        container.next_lamport = std::cmp::max(container.next_lamport, op.lamport + 1);
-        let id = Self::id(&op);
-        for _ in container.version_vector.len()..id.client_id + 1 {
-            container.version_vector.push(0);
+        if container.contains(Some(op.id)) {
+            return;
         }
-        assert!(container.version_vector[id.client_id] == id.clock);
-        rga::integrate::<RgaImpl>(container, op);
 
-        container.version_vector[id.client_id] = id.clock + 1;
+        container.next_lamport = std::cmp::max(container.next_lamport, op.lamport + 1);
+        rga::integrate::<Self>(container, op.clone());
+        container.advance(&op);
     }
 
     fn can_integrate(container: &Self::Container, op: &Self::OpUnit) -> bool {
@@ -230,6 +239,17 @@ impl TestFramework for RgaImpl {
 mod rga_impl_test {
     use super::*;
 
+    #[test]
+    fn local_ops_are_inserted_at_the_given_pos() {
+        let mut container = <RgaImpl>::new_container(0);
+        for pos in [0, 1, 0, 2, 1] {
+            let op = <RgaImpl>::new_op(&mut container, pos);
+            let id = op.id;
+            <RgaImpl>::integrate(&mut container, op);
+            assert_eq!(container.content[pos].id, id);
+        }
+    }
+
     #[test]
     fn run() {
         for i in 0..100 {
@@ -251,6 +271,65 @@ mod rga_impl_test {
         }
     }
 
+    #[test]
+    fn fork_merge() {
+        for seed in 0..100 {
+            crate::test::test_fork_merge::<RgaImpl>(seed, 3, 50);
+        }
+    }
+
+    #[test]
+    fn exhaustive() {
+        crate::test::explore::<RgaImpl>(2, 4, 2);
+        crate::test::explore::<RgaImpl>(3, 3, 2);
+    }
+
+    #[test]
+    fn delivery_orders() {
+        for seed in 0..20 {
+            crate::test::test_delivery_orders::<RgaImpl>(seed, 3, 200, 10);
+        }
+    }
+
+    #[test]
+    fn lossy_network() {
+        for seed in 0..10 {
+            crate::network::test_network::<RgaImpl>(seed, 4, 200, Default::default());
+        }
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        for seed in 0..20 {
+            crate::test::test_duplicates::<RgaImpl>(seed, 3, 300);
+        }
+    }
+
+    #[test]
+    fn tie_break_policies() {
+        use crate::{
+            dumb_common::peer_id,
+            test::tie,
+            tie_break::{Ascending, Descending, Hashed, Priority},
+        };
+        type Server = Priority<{ peer_id(1) }>;
+        // peer 0 has the greater id, but peer 1 wins the tie
+        assert_eq!(
+            tie::<RgaImpl<Server>>(0, 1),
+            tie::<RgaImpl<Descending>>(0, 1)
+        );
+        assert_ne!(
+            tie::<RgaImpl<Server>>(0, 1),
+            tie::<RgaImpl<Ascending>>(0, 1)
+        );
+        for seed in 0..30 {
+            crate::test::test::<RgaImpl<Descending>>(seed, 3, 300);
+            crate::test::test::<RgaImpl<Server>>(seed, 3, 300);
+            crate::test::test::<RgaImpl<Hashed>>(seed, 5, 300);
+            crate::test::test_fork_merge::<RgaImpl<Hashed>>(seed, 3, 50);
+        }
+    }
+
     use ctor::ctor;
     #[ctor]
     fn init_color_backtrace() {
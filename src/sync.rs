@@ -0,0 +1,461 @@
+//! A peer-to-peer sync protocol between two [ListDoc]s that doesn't depend on the transport.
+//!
+//! A [Session] is one side of a connection. It only consumes the received messages and
+//! produces the ones to send, so the caller moves them over whatever transport it has,
+//! encoded with [Encode]. The transport must deliver the messages of a connection in order,
+//! but a connection can drop at any time, losing the messages in flight.
+//!
+//! Both sides start with [Message::Hello] carrying their [Version], then each sends
+//! the ops the other misses, inserts and deletes alike, and new ones as they are made.
+//! The receiver acknowledges them with its version. After a disconnection, the next
+//! handshake tells each side what the other has, so nothing is lost or sent twice.
+
+use std::{collections::VecDeque, fmt::Debug, fmt::Display};
+
+use crate::{
+    codec::{read_u8, DecodeError, Encode},
+    list_doc::{includes, Algorithm, Event, ListDoc, ListOp},
+    peer::{PeerId, Version},
+    tie_break::TieBreak,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message<V> {
+    /// The first message of a connection
+    Hello {
+        version: Version,
+    },
+    Ops {
+        ops: Vec<ListOp<V>>,
+    },
+    /// The version of the receiver after applying [Message::Ops]
+    Ack {
+        version: Version,
+    },
+}
+
+impl<V: Encode> Encode for Message<V> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            Message::Hello { version } => {
+                buf.push(0);
+                version.encode_to(buf);
+            }
+            Message::Ops { ops } => {
+                buf.push(1);
+                ops.encode_to(buf);
+            }
+            Message::Ack { version } => {
+                buf.push(2);
+                version.encode_to(buf);
+            }
+        }
+    }
+
+    fn decode_from(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        match read_u8(buf)? {
+            0 => Ok(Message::Hello {
+                version: Version::decode_from(buf)?,
+            }),
+            1 => Ok(Message::Ops {
+                ops: Vec::decode_from(buf)?,
+            }),
+            2 => Ok(Message::Ack {
+                version: Version::decode_from(buf)?,
+            }),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncError {
+    Decode(DecodeError),
+    /// the message isn't allowed in the current state, e.g. ops before the handshake
+    Unexpected(State),
+}
+
+impl Display for SyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncError::Decode(err) => write!(f, "{}", err),
+            SyncError::Unexpected(state) => write!(f, "unexpected message when {:?}", state),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl From<DecodeError> for SyncError {
+    fn from(err: DecodeError) -> Self {
+        SyncError::Decode(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Disconnected,
+    /// sent [Message::Hello], waiting for the one of the peer
+    Connecting,
+    Connected,
+}
+
+#[derive(Debug, Clone)]
+pub struct Session<V> {
+    state: State,
+    /// the ops the peer is known to have, from its hello and acks
+    remote: Version,
+    /// the ops the peer has or that are sent to it on this connection
+    sent: Version,
+    outbox: VecDeque<Message<V>>,
+}
+
+impl<V> Default for Session<V> {
+    fn default() -> Self {
+        Session {
+            state: State::Disconnected,
+            remote: Version::new(),
+            sent: Version::new(),
+            outbox: VecDeque::new(),
+        }
+    }
+}
+
+impl<V: Clone + Debug + 'static> Session<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The version of the peer as far as this side knows
+    pub fn remote_version(&self) -> &Version {
+        &self.remote
+    }
+
+    /// Start a connection, or restart it after a disconnection
+    pub fn connect<A: Algorithm, P: TieBreak<PeerId> + 'static>(&mut self, doc: &ListDoc<A, V, P>) {
+        self.outbox.clear();
+        self.outbox.push_back(Message::Hello {
+            version: doc.version(),
+        });
+        self.state = State::Connecting;
+    }
+
+    /// The connection is lost, the unsent messages are dropped
+    pub fn disconnect(&mut self) {
+        self.outbox.clear();
+        self.sent = self.remote.clone();
+        self.state = State::Disconnected;
+    }
+
+    /// Whether the peer acknowledged all the ops of `doc`
+    pub fn is_synced<A: Algorithm, P: TieBreak<PeerId> + 'static>(
+        &self,
+        doc: &ListDoc<A, V, P>,
+    ) -> bool {
+        self.state == State::Connected && self.remote.dominates(&doc.version())
+    }
+
+    /// The next message to send. It also sends the ops of `doc` the peer misses,
+    /// so call it after local edits and after applying the ops of other peers.
+    pub fn poll<A: Algorithm, P: TieBreak<PeerId> + 'static>(
+        &mut self,
+        doc: &ListDoc<A, V, P>,
+    ) -> Option<Message<V>> {
+        let version = doc.version();
+        if self.state == State::Connected && !self.sent.dominates(&version) {
+            let ops = doc.ops_since(&self.sent);
+            self.sent = self.sent.join(&version);
+            self.outbox.push_back(Message::Ops { ops });
+        }
+
+        self.outbox.pop_front()
+    }
+
+    /// Handle a message of the peer, it returns the changes to the elements of `doc`
+    pub fn receive<A: Algorithm, P: TieBreak<PeerId> + 'static>(
+        &mut self,
+        doc: &mut ListDoc<A, V, P>,
+        message: Message<V>,
+    ) -> Result<Vec<Event<V>>, SyncError> {
+        let mut events = Vec::new();
+        match (self.state, message) {
+            (State::Disconnected, _) => return Err(SyncError::Unexpected(self.state)),
+            (_, Message::Hello { version }) => {
+                self.sent = version.clone();
+                self.remote = version;
+                self.state = State::Connected;
+            }
+            (State::Connected, Message::Ops { ops }) => {
+                let ids: Vec<_> = ops.iter().map(|op| op.id()).collect();
+                for op in ops {
+                    events.extend(doc.apply_remote(op));
+                }
+                // the peer has the ops that are integrated, so they're not echoed back.
+                // The ops that are dropped or still pending don't tell anything.
+                let version = doc.version();
+                for id in ids {
+                    if includes(&version, id) {
+                        self.remote.include(&id.client_id, id.clock);
+                        self.sent.include(&id.client_id, id.clock);
+                    }
+                }
+                self.outbox.push_back(Message::Ack { version });
+            }
+            (State::Connected, Message::Ack { version }) => {
+                self.remote = self.remote.join(&version);
+                self.sent = self.sent.join(&version);
+            }
+            (State::Connecting, _) => return Err(SyncError::Unexpected(self.state)),
+        }
+
+        Ok(events)
+    }
+
+    /// [Session::receive] an encoded message
+    pub fn receive_bytes<A: Algorithm, P: TieBreak<PeerId> + 'static>(
+        &mut self,
+        doc: &mut ListDoc<A, V, P>,
+        bytes: &[u8],
+    ) -> Result<Vec<Event<V>>, SyncError>
+    where
+        V: Encode,
+    {
+        let message = Message::decode(bytes)?;
+        self.receive(doc, message)
+    }
+}
+
+#[cfg(test)]
+mod sync_test {
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    use super::*;
+    use crate::list_doc::{DeleteOp, Fugue, InsertOp, OpId, Yata};
+
+    /// A replica with a session to one peer, connected by a pair of in-memory channels
+    struct Peer<A: Algorithm> {
+        doc: ListDoc<A, char>,
+        session: Session<char>,
+        tx: Sender<Vec<u8>>,
+        rx: Receiver<Vec<u8>>,
+        /// the ops this side has sent
+        n_sent: usize,
+    }
+
+    fn pair<A: Algorithm>(a: ListDoc<A, char>, b: ListDoc<A, char>) -> (Peer<A>, Peer<A>) {
+        let (a_tx, b_rx) = channel();
+        let (b_tx, a_rx) = channel();
+        let peer = |doc, tx, rx| Peer {
+            doc,
+            session: Session::new(),
+            tx,
+            rx,
+            n_sent: 0,
+        };
+        (peer(a, a_tx, a_rx), peer(b, b_tx, b_rx))
+    }
+
+    impl<A: Algorithm> Peer<A> {
+        fn flush(&mut self) -> bool {
+            let mut sent = false;
+            while let Some(message) = self.session.poll(&self.doc) {
+                if let Message::Ops { ops } = &message {
+                    self.n_sent += ops.len();
+                }
+                self.tx.send(message.encode()).unwrap();
+                sent = true;
+            }
+            sent
+        }
+
+        fn deliver(&mut self) -> bool {
+            let mut received = false;
+            while let Ok(bytes) = self.rx.try_recv() {
+                self.session.receive_bytes(&mut self.doc, &bytes).unwrap();
+                received = true;
+            }
+            received
+        }
+
+        /// Drop the connection and the messages in flight to this side
+        fn disconnect(&mut self) {
+            self.session.disconnect();
+            while self.rx.try_recv().is_ok() {}
+        }
+    }
+
+    /// Exchange messages until neither side has anything to say
+    fn run<A: Algorithm>(a: &mut Peer<A>, b: &mut Peer<A>) {
+        loop {
+            let mut busy = a.flush() | b.flush();
+            busy |= a.deliver() | b.deliver();
+            if !busy {
+                break;
+            }
+        }
+    }
+
+    fn content<A: Algorithm>(peer: &Peer<A>) -> String {
+        peer.doc.iter().collect()
+    }
+
+    #[test]
+    fn concurrent_edits_converge() {
+        let (mut a, mut b) = pair::<Fugue>(ListDoc::new(0), ListDoc::new(1));
+        a.doc.insert(0, "hello".chars());
+        b.doc.insert(0, "world".chars());
+        b.doc.delete(0..1);
+        a.session.connect(&a.doc);
+        b.session.connect(&b.doc);
+        run(&mut a, &mut b);
+        assert_eq!(content(&a), content(&b));
+        assert!(a.session.is_synced(&a.doc) && b.session.is_synced(&b.doc));
+
+        // live edits after the handshake
+        a.doc.delete(0..3);
+        b.doc.insert(2, "!!".chars());
+        run(&mut a, &mut b);
+        assert_eq!(content(&a), content(&b));
+        assert_eq!(a.doc.len(), 8);
+        // every op crosses the connection once
+        assert_eq!((a.n_sent, b.n_sent), (6, 8));
+    }
+
+    #[test]
+    fn resume_after_disconnection() {
+        let (mut a, mut b) = pair::<Yata>(ListDoc::new(0), ListDoc::new(1));
+        a.session.connect(&a.doc);
+        b.session.connect(&b.doc);
+        a.doc.insert(0, "abc".chars());
+        run(&mut a, &mut b);
+
+        // the ops are sent, but the connection drops before they arrive
+        a.doc.insert(3, "def".chars());
+        a.flush();
+        b.disconnect();
+        a.disconnect();
+        assert_eq!(a.session.state(), State::Disconnected);
+        a.doc.delete(0..1);
+        b.doc.insert(0, "xy".chars());
+        assert_eq!(
+            a.session.receive(&mut a.doc, Message::Ops { ops: vec![] }),
+            Err(SyncError::Unexpected(State::Disconnected))
+        );
+
+        a.session.connect(&a.doc);
+        b.session.connect(&b.doc);
+        let sent = a.n_sent;
+        run(&mut a, &mut b);
+        assert_eq!(content(&a), content(&b));
+        assert_eq!(content(&a), "xybcdef");
+        // the lost inserts and the delete are sent again
+        assert_eq!(a.n_sent - sent, 4);
+        assert!(a.session.is_synced(&a.doc) && b.session.is_synced(&b.doc));
+    }
+
+    #[test]
+    fn hostile_input() {
+        let mut doc = ListDoc::<Fugue, char>::new(0);
+        let mut ops = doc.insert(0, "ab".chars());
+        ops.extend(doc.delete(0..1));
+        let mut session = Session::new();
+        session.connect(&doc);
+        session.poll(&doc);
+
+        // huge peer ids and clocks are only keys
+        let hello: Message<char> = Message::Hello {
+            version: [(u128::MAX, usize::MAX), (1 << 100, 1)]
+                .into_iter()
+                .collect(),
+        };
+        session.receive_bytes(&mut doc, &hello.encode()).unwrap();
+        let id = |client_id, clock| OpId { client_id, clock };
+        let insert = ListOp::Insert(InsertOp {
+            id: id(u128::MAX, 0),
+            lamport: 9,
+            left: None,
+            right: None,
+            value: 'x',
+        });
+        let future = ListOp::Insert(InsertOp {
+            id: id(1 << 100, usize::MAX),
+            lamport: 9,
+            left: None,
+            right: None,
+            value: 'y',
+        });
+        // a delete of the delete op is skipped
+        let bogus = ListOp::Delete(DeleteOp {
+            id: id(5, 0),
+            lamport: 9,
+            targets: [(0, 1), (0, 2)].into_iter().collect(),
+        });
+        let message = Message::Ops {
+            ops: vec![insert, future, bogus],
+        };
+        let events = session.receive_bytes(&mut doc, &message.encode()).unwrap();
+        assert_eq!(events.len(), 1);
+        // the op that is still pending doesn't mark all the ops of its client as known
+        assert_eq!(session.remote_version().end(&(1 << 100)), 1);
+        assert_eq!(doc.len(), 2);
+        assert_eq!(
+            doc.version(),
+            [(0, 3), (5, 1), (u128::MAX, 1)].into_iter().collect()
+        );
+        // the ack, then the ops of peer 0 but not the one of the peer
+        let replies: Vec<_> = std::iter::from_fn(|| session.poll(&doc)).collect();
+        assert_eq!(replies.len(), 2);
+        assert!(matches!(&replies[1], Message::Ops { ops } if ops.len() == 3));
+
+        // a span that ends after usize::MAX
+        let mut bytes = vec![1, 1, 1];
+        id(5, 0).encode_to(&mut bytes);
+        9u32.encode_to(&mut bytes);
+        vec![(0u128, vec![(1usize, 1usize), (usize::MAX, 1)])].encode_to(&mut bytes);
+        assert_eq!(
+            session.receive_bytes(&mut doc, &bytes),
+            Err(SyncError::Decode(DecodeError::Overflow))
+        );
+    }
+
+    #[test]
+    fn handshake_first() {
+        let mut doc = ListDoc::<Fugue, char>::new(0);
+        let ops = ListDoc::<Fugue, char>::new(1).insert(0, ['x']);
+        let mut session = Session::new();
+        session.connect(&doc);
+        assert_eq!(
+            session.receive(&mut doc, Message::Ops { ops: ops.clone() }),
+            Err(SyncError::Unexpected(State::Connecting))
+        );
+        assert_eq!(
+            session.receive_bytes(&mut doc, &[9]),
+            Err(SyncError::Decode(DecodeError::InvalidTag(9)))
+        );
+
+        let hello: Message<char> = Message::Hello {
+            version: Version::new(),
+        };
+        session.receive_bytes(&mut doc, &hello.encode()).unwrap();
+        let message = Message::Ops { ops };
+        assert_eq!(Message::decode(&message.encode()), Ok(message.clone()));
+        assert_eq!(session.receive(&mut doc, message).unwrap().len(), 1);
+        assert_eq!(
+            session.poll(&doc),
+            Some(Message::Hello {
+                version: Version::new()
+            })
+        );
+        assert_eq!(
+            session.poll(&doc),
+            Some(Message::Ack {
+                version: [(1, 1)].into_iter().collect()
+            })
+        );
+        assert_eq!(session.poll(&doc), None);
+    }
+}
@@ -1,13 +1,20 @@
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    sync::{Mutex, MutexGuard},
+};
 
 use rand::{rngs::StdRng, Rng};
 
-use crate::crdt::ListCrdt;
+use crate::crdt::{GetOp, ListCrdt};
 
 pub trait TestFramework: ListCrdt {
     type DeleteOp: Clone;
     fn is_content_eq(a: &Self::Container, b: &Self::Container) -> bool;
     fn new_container(id: usize) -> Self::Container;
+    /// A copy of `container` that creates its new ops as client `id`.
+    /// `id` must not be used by any other container. Forking is O(1), but the first edit
+    /// of either copy may clone its ops in O(n).
+    fn fork(container: &Self::Container, id: usize) -> Self::Container;
     /// pos is just a hint, it may not be a valid position
     fn new_op(container: &mut Self::Container, pos: usize) -> Self::OpUnit;
 
@@ -15,6 +22,8 @@ pub trait TestFramework: ListCrdt {
     fn integrate_delete_op(container: &mut Self::Container, op: Self::DeleteOp);
     fn integrate(container: &mut Self::Container, op: Self::OpUnit);
     fn can_integrate(container: &Self::Container, op: &Self::OpUnit) -> bool;
+    /// Whether the ops deleted by `op` are integrated
+    fn can_integrate_delete_op(container: &Self::Container, op: &Self::DeleteOp) -> bool;
 }
 
 #[derive(Clone, Debug)]
@@ -51,13 +60,86 @@ impl Action {
     }
 }
 
+/// The received ops that wait for their dependencies. A delete is integrated once,
+/// as soon as the ops it deletes are.
+#[derive(Debug)]
+pub(crate) struct Pending<T: TestFramework> {
+    ops: Vec<T::OpUnit>,
+    del_ops: Vec<T::DeleteOp>,
+}
+
+impl<T: TestFramework> Clone for Pending<T> {
+    fn clone(&self) -> Self {
+        Pending {
+            ops: self.ops.clone(),
+            del_ops: self.del_ops.clone(),
+        }
+    }
+}
+
+impl<T: TestFramework> Pending<T> {
+    pub(crate) fn new() -> Self {
+        Pending {
+            ops: Vec::new(),
+            del_ops: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ops.is_empty() && self.del_ops.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, op: T::OpUnit) {
+        self.ops.push(op);
+    }
+
+    pub(crate) fn push_delete(&mut self, op: T::DeleteOp) {
+        self.del_ops.push(op);
+    }
+
+    /// Integrate the ops whose dependencies are in `container`, until none is left that can be.
+    /// It returns the inserts in the order they are integrated, and drops the integrated ones.
+    pub(crate) fn apply(&mut self, container: &mut T::Container) -> Vec<T::OpUnit> {
+        let mut integrated = Vec::new();
+        loop {
+            let len = self.ops.len();
+            for op in std::mem::take(&mut self.ops) {
+                if T::integrated(container, T::id(&op)) {
+                    continue;
+                }
+
+                if T::can_integrate(container, &op) {
+                    integrated.push(op.clone());
+                    T::integrate(container, op);
+                } else {
+                    self.ops.push(op);
+                }
+            }
+            if self.ops.len() == len {
+                break;
+            }
+        }
+
+        // deletes don't unblock anything
+        self.del_ops.retain(|op| {
+            if !T::can_integrate_delete_op(container, op) {
+                return true;
+            }
+
+            T::integrate_delete_op(container, op.clone());
+            false
+        });
+        integrated
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Actor<T: TestFramework> {
     container: T::Container,
     idx: usize,
     ops: Vec<Vec<T::OpUnit>>,
     del_ops: Vec<Vec<T::DeleteOp>>,
-    pending_ops: Vec<T::OpUnit>,
+    pending: Pending<T>,
     _phantom: PhantomData<T>,
 }
 
@@ -67,7 +149,7 @@ impl<T: TestFramework> Actor<T> {
             container: T::new_container(idx as usize),
             idx: idx as usize,
             ops: vec![Default::default(); n_container as usize],
-            pending_ops: Vec::new(),
+            pending: Pending::new(),
             del_ops: vec![Default::default(); n_container as usize],
             _phantom: PhantomData,
         }
@@ -90,20 +172,6 @@ impl<T: TestFramework> Actor<T> {
         }
     }
 
-    fn apply_pending(&mut self) {
-        let mut pending = std::mem::take(&mut self.pending_ops);
-        while !pending.is_empty() {
-            let current = std::mem::take(&mut pending);
-            for op in current {
-                if T::can_integrate(&self.container, &op) {
-                    T::integrate(&mut self.container, op.clone());
-                } else {
-                    pending.push(op.clone());
-                }
-            }
-        }
-    }
-
     fn sync(&mut self, other: &Self) {
         for (op_arr_this, op_arr_other) in self.ops.iter_mut().zip(other.ops.iter()) {
             if op_arr_this.len() >= op_arr_other.len() {
@@ -112,15 +180,10 @@ impl<T: TestFramework> Actor<T> {
 
             for op in op_arr_other.iter().skip(op_arr_this.len()) {
                 op_arr_this.push(op.clone());
-                if T::can_integrate(&self.container, op) {
-                    T::integrate(&mut self.container, op.clone());
-                } else {
-                    self.pending_ops.push(op.clone());
-                }
+                self.pending.push(op.clone());
             }
         }
 
-        self.apply_pending();
         // repeat the same logic with delete op
         for (op_arr_this, op_arr_other) in self.del_ops.iter_mut().zip(other.del_ops.iter()) {
             if op_arr_this.len() >= op_arr_other.len() {
@@ -129,9 +192,51 @@ impl<T: TestFramework> Actor<T> {
 
             for op in op_arr_other.iter().skip(op_arr_this.len()) {
                 op_arr_this.push(op.clone());
-                T::integrate_delete_op(&mut self.container, op.clone());
+                self.pending.push_delete(op.clone());
             }
         }
+        self.pending.apply(&mut self.container);
+    }
+
+    /// Every actor receives the ops of all the others
+    fn sync_all(actors: &mut [Self]) {
+        for to in 0..actors.len() {
+            for from in 0..actors.len() {
+                if from != to {
+                    let (to, from) = arref::array_mut_ref!(actors, [to, from]);
+                    to.sync(from);
+                }
+            }
+        }
+    }
+
+    /// Whether `other` has ops that this actor hasn't received
+    fn misses(&self, other: &Self) -> bool {
+        let ops = self.ops.iter().zip(other.ops.iter());
+        let del_ops = self.del_ops.iter().zip(other.del_ops.iter());
+        ops.map(|(a, b)| a.len() < b.len())
+            .chain(del_ops.map(|(a, b)| a.len() < b.len()))
+            .any(|x| x)
+    }
+
+    /// A branch that starts with everything this actor knows
+    fn fork(&self, idx: u8) -> Self {
+        Actor {
+            container: T::fork(&self.container, idx as usize),
+            idx: idx as usize,
+            ops: self.ops.clone(),
+            pending: self.pending.clone(),
+            del_ops: self.del_ops.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn random_edit(&mut self, rng: &mut impl Rng) {
+        if rng.gen_bool(0.8) {
+            self.new_op(rng.gen::<u8>() as usize);
+        } else {
+            self.new_del_op(rng.gen_range(0..16), rng.gen_range(1..4));
+        }
     }
 
     fn new_op(&mut self, pos: usize) {
@@ -154,6 +259,23 @@ impl<T: TestFramework> Actor<T> {
         }
     }
 
+    /// Like [Actor::run], but the edits include deletions
+    fn run_edits(actors: &mut [Self], rng: &mut impl Rng, n_actions: usize) {
+        let n_container = actors.len();
+        for _ in 0..n_actions {
+            if rng.gen_bool(0.1) {
+                let from = rng.gen_range(0..n_container) as u8;
+                let to = rng.gen_range(0..n_container) as u8;
+                if from != to {
+                    Self::run_action(Action::Sync { from, to }, actors);
+                }
+            } else {
+                let i = rng.gen_range(0..n_container);
+                actors[i].random_edit(rng);
+            }
+        }
+    }
+
     fn check(containers: &mut [Self]) {
         for i in 0..(containers.len() - 1) {
             let (a, b) = arref::array_mut_ref!(containers, [i, i + 1]);
@@ -195,6 +317,360 @@ pub fn test<T: TestFramework>(seed: u64, n_container: usize, round: usize) {
     Actor::check(&mut containers);
 }
 
+/// Fork `n_branches` branches from a common base and edit them separately. Then merge
+/// them into fresh forks of the base in different orders, which must all end up equal.
+pub fn test_fork_merge<T: TestFramework>(seed: u64, n_branches: usize, round: usize) {
+    let mut rng: StdRng = rand::SeedableRng::seed_from_u64(seed);
+    // the base, the branches and a merge for every order
+    let n_container = (1 + 3 * n_branches) as u8;
+    let mut base: Actor<T> = Actor::new(0, n_container);
+    for _ in 0..round {
+        base.random_edit(&mut rng);
+    }
+
+    let mut branches: Vec<Actor<T>> = (1..=n_branches).map(|i| base.fork(i as u8)).collect();
+    for _ in 0..round {
+        let i = rng.gen_range(0..n_branches);
+        branches[i].random_edit(&mut rng);
+    }
+
+    // every rotation of the branches, forwards and backwards
+    let mut merged: Vec<Actor<T>> = Vec::new();
+    for start in 0..n_branches {
+        for reverse in [false, true] {
+            let mut actor = base.fork((1 + n_branches + merged.len()) as u8);
+            for k in 0..n_branches {
+                let i = match reverse {
+                    false => (start + k) % n_branches,
+                    true => (start + n_branches - k) % n_branches,
+                };
+                actor.sync(&branches[i]);
+            }
+            // merging a branch again changes nothing
+            actor.sync(&branches[start]);
+            merged.push(actor);
+        }
+    }
+
+    for actor in merged.iter().skip(1) {
+        if !T::is_content_eq(&merged[0].container, &actor.container) {
+            dbg!(&merged[0].container);
+            dbg!(&actor.container);
+            panic!("Merges in different orders are not equal");
+        }
+    }
+
+    // after the check, the last branch has synced with all the others
+    Actor::check(&mut branches);
+    assert!(T::is_content_eq(
+        &branches[n_branches - 1].container,
+        &merged[0].container
+    ));
+}
+
+/// Run random actions, then merge every pair of replicas with `merge`, which must
+/// give the same content as syncing them op by op
+pub fn test_bulk_merge<T: TestFramework>(
+    seed: u64,
+    n_container: usize,
+    round: usize,
+    merge: fn(&T::Container, &T::Container) -> T::Container,
+) {
+    let mut rng: StdRng = rand::SeedableRng::seed_from_u64(seed);
+    let mut actors: Vec<Actor<T>> = (0..n_container)
+        .map(|i| Actor::new(i as u8, n_container as u8))
+        .collect();
+    Actor::run_edits(&mut actors, &mut rng, round);
+
+    for (i, a) in actors.iter().enumerate() {
+        for b in actors.iter() {
+            let merged = merge(&a.container, &b.container);
+            let mut synced = a.fork(i as u8);
+            synced.sync(b);
+            if !T::is_content_eq(&merged, &synced.container) {
+                dbg!(&merged);
+                dbg!(&synced.container);
+                panic!("The bulk merge is not the same as syncing");
+            }
+        }
+    }
+}
+
+/// Check every scenario of `n_edits` inserts and deletes on `n_container` replicas with up
+/// to `n_syncs` syncs between them, so the algorithm is correct up to that bound.
+/// Returns the number of scenarios.
+///
+/// In every scenario
+///
+/// - a local insert lands at the position it was made at,
+/// - the order of two ops never changes once a replica has both,
+/// - the replicas converge after syncing,
+/// - integrating the ops in every causal order gives the same content.
+pub fn explore<T: TestFramework>(n_container: usize, n_edits: usize, n_syncs: usize) -> usize {
+    let mut count = 0;
+    explore_from::<T>(n_container, &mut Vec::new(), n_edits, n_syncs, &mut count);
+    count
+}
+
+fn explore_from<T: TestFramework>(
+    n_container: usize,
+    prefix: &mut Vec<Action>,
+    edits_left: usize,
+    syncs_left: usize,
+    count: &mut usize,
+) {
+    // the final syncs are done by check_scenario, so a scenario ends with an edit
+    if edits_left == 0 {
+        check_scenario::<T>(n_container, prefix);
+        *count += 1;
+        return;
+    }
+
+    let actors = replay::<T>(n_container, prefix, |_, _| {});
+    let mut next = Vec::new();
+    for (i, actor) in actors.iter().enumerate() {
+        let len = order::<T>(&actor.container).len();
+        for pos in 0..=len {
+            next.push(Action::NewOp {
+                client_id: i as u8,
+                pos: pos as u8,
+            });
+        }
+        for pos in 0..len {
+            next.push(Action::Delete {
+                client_id: i as u8,
+                pos: pos as u8,
+                len: 1,
+            });
+        }
+    }
+
+    if syncs_left > 0 {
+        for (to, to_actor) in actors.iter().enumerate() {
+            for (from, from_actor) in actors.iter().enumerate() {
+                if from != to && to_actor.misses(from_actor) {
+                    next.push(Action::Sync {
+                        from: from as u8,
+                        to: to as u8,
+                    });
+                }
+            }
+        }
+    }
+
+    for action in next {
+        let is_sync = matches!(action, Action::Sync { .. });
+        prefix.push(action);
+        match is_sync {
+            true => explore_from::<T>(n_container, prefix, edits_left, syncs_left - 1, count),
+            false => explore_from::<T>(n_container, prefix, edits_left - 1, syncs_left, count),
+        }
+        prefix.pop();
+    }
+}
+
+/// Run `actions` on fresh actors, `after` is called with the actors after every action
+fn replay<T: TestFramework>(
+    n_container: usize,
+    actions: &[Action],
+    mut after: impl FnMut(&Action, &[Actor<T>]),
+) -> Vec<Actor<T>> {
+    let mut actors: Vec<Actor<T>> = (0..n_container)
+        .map(|i| Actor::new(i as u8, n_container as u8))
+        .collect();
+    for action in actions {
+        Actor::run_action(action.clone(), &mut actors);
+        after(action, &actors);
+    }
+    actors
+}
+
+fn check_scenario<T: TestFramework>(n_container: usize, actions: &[Action]) {
+    let mut seen: Vec<Vec<T::OpId>> = Vec::new();
+    let mut orders: Vec<Vec<T::OpId>> = vec![Vec::new(); n_container];
+    let mut actors = replay::<T>(n_container, actions, |action, actors| {
+        if let Action::NewOp { client_id, pos } = action {
+            let i = *client_id as usize;
+            let before = &orders[i];
+            let after = order::<T>(&actors[i].container);
+            let id = T::id(actors[i].ops[i].last().unwrap());
+            let mut expected = before.clone();
+            expected.insert(*pos as usize % (before.len() + 1), id);
+            assert_eq!(
+                after, expected,
+                "{:?} is not inserted at its position",
+                action
+            );
+        }
+
+        for (i, actor) in actors.iter().enumerate() {
+            orders[i] = order::<T>(&actor.container);
+        }
+        seen.extend(orders.iter().cloned());
+    });
+
+    Actor::sync_all(&mut actors);
+
+    for actor in actors.iter().skip(1) {
+        assert!(
+            T::is_content_eq(&actors[0].container, &actor.container),
+            "replicas diverge after {:?}",
+            actions
+        );
+    }
+
+    let all = order::<T>(&actors[0].container);
+    for order in seen {
+        let mut rest = all.iter();
+        assert!(
+            order.iter().all(|id| rest.any(|x| x == id)),
+            "the order of {:?} changed after {:?}",
+            order,
+            actions
+        );
+    }
+
+    let ops: Vec<T::OpUnit> = actors[0].ops.iter().flatten().cloned().collect();
+    let del_ops: Vec<T::DeleteOp> = actors[0].del_ops.iter().flatten().cloned().collect();
+    let base = T::new_container(n_container);
+    for_each_causal_order::<T>(
+        &base,
+        n_container,
+        &ops,
+        &mut vec![false; ops.len()],
+        &mut |container| {
+            for op in del_ops.iter() {
+                T::integrate_delete_op(container, op.clone());
+            }
+            assert!(
+                T::is_content_eq(&actors[0].container, container),
+                "a delivery order gives different content after {:?}",
+                actions
+            );
+        },
+    );
+}
+
+/// Call `f` with the result of integrating `ops` into `container` in every causal order
+fn for_each_causal_order<T: TestFramework>(
+    container: &T::Container,
+    id: usize,
+    ops: &[T::OpUnit],
+    used: &mut Vec<bool>,
+    f: &mut impl FnMut(&mut T::Container),
+) {
+    if used.iter().all(|x| *x) {
+        f(&mut T::fork(container, id));
+        return;
+    }
+
+    for i in 0..ops.len() {
+        if used[i] || !T::can_integrate(container, &ops[i]) {
+            continue;
+        }
+
+        let mut next = T::fork(container, id);
+        T::integrate(&mut next, ops[i].clone());
+        used[i] = true;
+        for_each_causal_order::<T>(&next, id, ops, used, f);
+        used[i] = false;
+    }
+}
+
+/// Run random actions, then deliver every op again to every actor, which must change nothing
+pub fn test_duplicates<T: TestFramework>(seed: u64, n_container: usize, round: usize) {
+    let mut rng: StdRng = rand::SeedableRng::seed_from_u64(seed);
+    let mut actors: Vec<Actor<T>> = (0..n_container)
+        .map(|i| Actor::new(i as u8, n_container as u8))
+        .collect();
+    Actor::run(&mut actors, &mut rng, round);
+    for actor in actors.iter_mut() {
+        let before = T::fork(&actor.container, actor.idx);
+        for op in actor.ops.iter().flatten() {
+            assert!(T::integrated(&actor.container, T::id(op)));
+            T::integrate(&mut actor.container, op.clone());
+        }
+        if !T::is_content_eq(&before, &actor.container) {
+            dbg!(&before);
+            dbg!(&actor.container);
+            panic!("Integrating an op twice changes the content");
+        }
+    }
+}
+
+/// Run random inserts, deletions and syncs, then integrate all the ops into fresh containers
+/// in `n_orders` random causal orders, with the deletions anywhere after the ops they delete.
+/// Every order must give the content of the synced replicas.
+pub fn test_delivery_orders<T: TestFramework>(
+    seed: u64,
+    n_container: usize,
+    round: usize,
+    n_orders: usize,
+) {
+    let mut rng: StdRng = rand::SeedableRng::seed_from_u64(seed);
+    let mut actors: Vec<Actor<T>> = (0..n_container)
+        .map(|i| Actor::new(i as u8, n_container as u8))
+        .collect();
+    Actor::run_edits(&mut actors, &mut rng, round);
+    Actor::sync_all(&mut actors);
+
+    let ops: Vec<T::OpUnit> = actors[0].ops.iter().flatten().cloned().collect();
+    let del_ops: Vec<T::DeleteOp> = actors[0].del_ops.iter().flatten().cloned().collect();
+    assert!(!del_ops.is_empty(), "no deletions to deliver");
+    for _ in 0..n_orders {
+        let mut container = T::new_container(n_container);
+        let mut rest = ops.clone();
+        let mut rest_del = del_ops.clone();
+        // a delete is delivered at any point after the ops it deletes
+        while !rest.is_empty() || !rest_del.is_empty() {
+            let ready: Vec<usize> = (0..rest.len())
+                .filter(|&i| T::can_integrate(&container, &rest[i]))
+                .collect();
+            let ready_del: Vec<usize> = (0..rest_del.len())
+                .filter(|&i| T::can_integrate_delete_op(&container, &rest_del[i]))
+                .collect();
+            let n_ready = ready.len() + ready_del.len();
+            assert!(n_ready > 0, "some ops miss their dependencies");
+            let i = rng.gen_range(0..n_ready);
+            if i < ready.len() {
+                let op = rest.swap_remove(ready[i]);
+                T::integrate(&mut container, op);
+            } else {
+                let op = rest_del.swap_remove(ready_del[i - ready.len()]);
+                T::integrate_delete_op(&mut container, op);
+            }
+        }
+
+        if !T::is_content_eq(&actors[0].container, &container) {
+            dbg!(&actors[0].container);
+            dbg!(&container);
+            panic!("The delivery order changes the content");
+        }
+    }
+}
+
+/// The ids of all the ops in `container` in list order, including the deleted ones
+fn order<T: TestFramework>(container: &T::Container) -> Vec<T::OpId> {
+    T::iter(container, None, None)
+        .map(|x| T::id(x.get_op_ref()))
+        .collect()
+}
+
+/// The list order of two concurrent inserts at the same position by the containers `a`
+/// and `b`, which is decided by the tie-break policy of `T`
+pub fn tie<T: TestFramework>(a: usize, b: usize) -> Vec<T::OpId> {
+    let mut x = T::new_container(a);
+    let mut y = T::new_container(b);
+    let op_x = T::new_op(&mut x, 0);
+    let op_y = T::new_op(&mut y, 0);
+    T::integrate(&mut x, op_x.clone());
+    T::integrate(&mut y, op_y.clone());
+    T::integrate(&mut x, op_y);
+    T::integrate(&mut y, op_x);
+    assert!(T::is_content_eq(&x, &y));
+    order::<T>(&x)
+}
+
 pub fn test_actions<T: TestFramework>(n_container: usize, actions: Vec<Action>) {
     let mut containers: Vec<Actor<T>> = Vec::new();
     for i in 0..n_container {
@@ -247,3 +723,322 @@ pub fn normalize_actions(actions: &mut [Action], n_container: usize, content_len
         action.normalize(n_container, content_len);
     }
 }
+
+/// Shrink `actions` that make [test_with_actions] fail, then print a test that reproduces
+/// the failure with [test_actions], ready to paste into the tests of the impl, which
+/// `use crate::test::Action::*` like the `simple` tests. Returns the number of containers
+/// and the shrunk actions.
+///
+/// The failures are caught with a silent panic hook. The hook is global, so the panics of
+/// other threads are silent too while it runs, and the calls of `minimize` are serialized.
+/// Run it alone, e.g. with `--test-threads=1`, to see the messages of the other tests.
+///
+/// # Panics
+///
+/// If [test_with_actions] doesn't fail on `actions`
+pub fn minimize<T: TestFramework>(
+    n_container: usize,
+    content_len: usize,
+    mut actions: Vec<Action>,
+) -> (usize, Vec<Action>) {
+    normalize_actions(&mut actions, n_container, content_len);
+    // the same syncs as test_with_actions, so test_actions can replay them
+    for action in actions.iter_mut() {
+        if let Action::Sync { from, to } = action {
+            if from == to {
+                *from = (*from + 1) % n_container as u8;
+            }
+        }
+    }
+
+    let ans = {
+        let _quiet = QuietPanics::new();
+        let fails = |n_container: usize, actions: &[Action]| {
+            let actions = actions.to_vec();
+            std::panic::catch_unwind(|| test_actions::<T>(n_container, actions)).is_err()
+        };
+        let failed = fails(n_container, &actions);
+        failed.then(|| shrink(n_container, actions, fails))
+    };
+
+    let (n_container, actions) = ans.expect("the actions don't fail");
+    println!("{}", rust_test::<T>(n_container, &actions));
+    (n_container, actions)
+}
+
+/// Replaces the panic hook with a silent one until it's dropped, even by a panic.
+/// Only one exists at a time, so the hook it restores is never a silent one.
+struct QuietPanics {
+    hook: Option<Box<dyn FnOnce()>>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl QuietPanics {
+    fn new() -> Self {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        QuietPanics {
+            hook: Some(Box::new(move || std::panic::set_hook(hook))),
+            _lock: lock,
+        }
+    }
+}
+
+impl Drop for QuietPanics {
+    fn drop(&mut self) {
+        if let Some(restore) = self.hook.take() {
+            restore();
+        }
+    }
+}
+
+/// Simplify `actions` until no step keeps `fails` true: drop chunks of actions, lower the
+/// positions and lengths, and merge clients into the lower ones
+fn shrink(
+    mut n_container: usize,
+    mut actions: Vec<Action>,
+    mut fails: impl FnMut(usize, &[Action]) -> bool,
+) -> (usize, Vec<Action>) {
+    loop {
+        let len = actions.len();
+        let mut changed = false;
+
+        // drop chunks, from halves down to single actions
+        let mut chunk = std::cmp::max(actions.len() / 2, 1);
+        while chunk > 0 {
+            let mut start = 0;
+            while start < actions.len() {
+                let mut candidate = actions.clone();
+                candidate.drain(start..std::cmp::min(start + chunk, actions.len()));
+                if fails(n_container, &candidate) {
+                    actions = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+
+        // lower the positions and lengths
+        for i in 0..actions.len() {
+            for simpler in simplify(&actions[i]) {
+                let mut candidate = actions.clone();
+                candidate[i] = simpler;
+                if fails(n_container, &candidate) {
+                    actions = candidate;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+
+        // merge every client into a lower one, the syncs between them are dropped
+        for from in (1..n_container as u8).rev() {
+            for to in 0..from {
+                let candidate = merge_client(&actions, from, to);
+                if fails(n_container, &candidate) {
+                    actions = candidate;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+
+        // the clients that are never used after the merges
+        while n_container > 1
+            && actions
+                .iter()
+                .all(|x| !uses_client(x, n_container as u8 - 1))
+            && fails(n_container - 1, &actions)
+        {
+            n_container -= 1;
+            changed = true;
+        }
+
+        if !changed && actions.len() == len {
+            return (n_container, actions);
+        }
+    }
+}
+
+/// The simpler versions of `action`, the simplest first
+fn simplify(action: &Action) -> Vec<Action> {
+    let lower = |x: u8| {
+        [0, x / 2, x.saturating_sub(1)]
+            .into_iter()
+            .filter(move |&y| y < x)
+    };
+    match *action {
+        Action::Sync { .. } => Vec::new(),
+        Action::NewOp { client_id, pos } => lower(pos)
+            .map(|pos| Action::NewOp { client_id, pos })
+            .collect(),
+        Action::Delete {
+            client_id,
+            pos,
+            len,
+        } => lower(len)
+            .filter(|&len| len > 0)
+            .map(|len| Action::Delete {
+                client_id,
+                pos,
+                len,
+            })
+            .chain(lower(pos).map(|pos| Action::Delete {
+                client_id,
+                pos,
+                len,
+            }))
+            .collect(),
+    }
+}
+
+fn merge_client(actions: &[Action], from: u8, to: u8) -> Vec<Action> {
+    let map = |x: u8| if x == from { to } else { x };
+    actions
+        .iter()
+        .filter_map(|action| match *action {
+            Action::Sync { from: a, to: b } => (map(a) != map(b)).then_some(Action::Sync {
+                from: map(a),
+                to: map(b),
+            }),
+            Action::NewOp { client_id, pos } => Some(Action::NewOp {
+                client_id: map(client_id),
+                pos,
+            }),
+            Action::Delete {
+                client_id,
+                pos,
+                len,
+            } => Some(Action::Delete {
+                client_id: map(client_id),
+                pos,
+                len,
+            }),
+        })
+        .collect()
+}
+
+fn uses_client(action: &Action, client: u8) -> bool {
+    match *action {
+        Action::Sync { from, to } => from == client || to == client,
+        Action::NewOp { client_id, .. } | Action::Delete { client_id, .. } => client_id == client,
+    }
+}
+
+/// A test that runs `actions` with [test_actions], formatted like rustfmt does
+pub fn rust_test<T: TestFramework>(n_container: usize, actions: &[Action]) -> String {
+    let mut ans = String::new();
+    ans.push_str("    #[test]\n    fn minimized() {\n");
+    ans.push_str(&format!(
+        "        crate::test::test_actions::<{}>(\n            {},\n            vec![\n",
+        std::any::type_name::<T>().replace("crdt_list::", "crate::"),
+        n_container
+    ));
+    for action in actions {
+        let fields = match action {
+            Action::Sync { from, to } => {
+                ans.push_str(&format!(
+                    "                Sync {{ from: {}, to: {} }},\n",
+                    from, to
+                ));
+                continue;
+            }
+            Action::NewOp { client_id, pos } => {
+                format!(
+                    "NewOp {{\n{f}client_id: {client_id},\n{f}pos: {pos},\n",
+                    f = " ".repeat(20)
+                )
+            }
+            Action::Delete {
+                client_id,
+                pos,
+                len,
+            } => format!(
+                "Delete {{\n{f}client_id: {client_id},\n{f}pos: {pos},\n{f}len: {len},\n",
+                f = " ".repeat(20)
+            ),
+        };
+        ans.push_str(&format!("                {}                }},\n", fields));
+    }
+    ans.push_str("            ],\n        );\n    }\n");
+    ans
+}
+
+#[cfg(test)]
+mod minimize_test {
+    use super::*;
+    use crate::fugue_dumb_impl::FugueImpl;
+    use Action::*;
+
+    #[test]
+    fn shrink_keeps_the_failure() {
+        let mut rng: StdRng = rand::SeedableRng::seed_from_u64(0);
+        let actions: Vec<Action> = (0..200)
+            .map(|_| Actor::<FugueImpl>::gen(&mut rng, 4))
+            .collect();
+        // fails when a client that isn't 0 inserts after a sync into client 0
+        let fails = |_: usize, actions: &[Action]| {
+            let synced = actions.iter().position(|x| matches!(x, Sync { to: 0, .. }));
+            synced.is_some_and(|i| {
+                actions[i..]
+                    .iter()
+                    .any(|x| matches!(x, NewOp { client_id, .. } if *client_id != 0))
+            })
+        };
+        assert!(fails(4, &actions));
+
+        let (n_container, actions) = shrink(4, actions, fails);
+        assert_eq!(n_container, 2);
+        assert_eq!(actions.len(), 2);
+        assert!(matches!(actions[0], Sync { from: 1, to: 0 }));
+        assert!(matches!(
+            actions[1],
+            NewOp {
+                client_id: 1,
+                pos: 0
+            }
+        ));
+    }
+
+    #[test]
+    fn prints_a_test() {
+        let actions = vec![
+            Sync { from: 0, to: 1 },
+            NewOp {
+                client_id: 1,
+                pos: 3,
+            },
+        ];
+        let expected = "    #[test]
+    fn minimized() {
+        crate::test::test_actions::<crate::fugue_dumb_impl::FugueImpl>(
+            2,
+            vec![
+                Sync { from: 0, to: 1 },
+                NewOp {
+                    client_id: 1,
+                    pos: 3,
+                },
+            ],
+        );
+    }
+";
+        assert_eq!(rust_test::<FugueImpl>(2, &actions), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "the actions don't fail")]
+    fn passing_actions_cannot_be_minimized() {
+        minimize::<FugueImpl>(
+            2,
+            10,
+            vec![NewOp {
+                client_id: 0,
+                pos: 0,
+            }],
+        );
+    }
+}
@@ -0,0 +1,453 @@
+//! A text CRDT built on [ListDoc], where every char is an element.
+//!
+//! Besides the list, it keeps an index over the visible text that caches the UTF-8, UTF-16
+//! and char lengths of its chunks, so positions can be converted between the different
+//! units and op ids in O(log n).
+//!
+//! The index stores the text in chunks of up to [MAX_CHUNK] chars. A chunk is a run of
+//! visible chars with consecutive ids of one client, like the chars of a typed or pasted
+//! string, so its ids are its first id and an offset. An insert or a delete in the middle
+//! of a chunk splits it, and a char typed right after a chunk is appended to it.
+//! In the list, every char is still its own element with its own insert op, since the
+//! algorithms can't split their elements.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    ops::{Add, Range, Sub},
+};
+
+use crate::{
+    index_tree::{IndexTree, NodeId},
+    list_doc::{Algorithm, Event, ListDoc, ListOp, OpId},
+    peer::PeerId,
+};
+
+/// The most chars in a chunk, which bounds the scans inside a chunk
+pub const MAX_CHUNK: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Utf8,
+    Utf16,
+    Char,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextLen {
+    pub utf8: usize,
+    pub utf16: usize,
+    pub chars: usize,
+}
+
+impl TextLen {
+    pub fn of(c: char) -> Self {
+        TextLen {
+            utf8: c.len_utf8(),
+            utf16: c.len_utf16(),
+            chars: 1,
+        }
+    }
+
+    pub fn of_str(s: &str) -> Self {
+        s.chars()
+            .map(TextLen::of)
+            .fold(TextLen::default(), Add::add)
+    }
+
+    pub fn get(&self, unit: Unit) -> usize {
+        match unit {
+            Unit::Utf8 => self.utf8,
+            Unit::Utf16 => self.utf16,
+            Unit::Char => self.chars,
+        }
+    }
+}
+
+impl Add for TextLen {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        TextLen {
+            utf8: self.utf8 + rhs.utf8,
+            utf16: self.utf16 + rhs.utf16,
+            chars: self.chars + rhs.chars,
+        }
+    }
+}
+
+impl Sub for TextLen {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        TextLen {
+            utf8: self.utf8 - rhs.utf8,
+            utf16: self.utf16 - rhs.utf16,
+            chars: self.chars - rhs.chars,
+        }
+    }
+}
+
+/// A change to the text, with positions in the unit passed to [TextDoc::apply_remote]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextEvent {
+    Insert { pos: usize, text: String },
+    Delete { pos: usize, len: usize },
+}
+
+/// A run of visible chars, see the [module docs](self)
+#[derive(Debug, Clone)]
+struct Chunk {
+    /// the id of the first char, the next chars have the next clocks
+    id: OpId,
+    text: String,
+}
+
+impl Chunk {
+    fn id_at(&self, offset: usize) -> OpId {
+        OpId {
+            client_id: self.id.client_id,
+            clock: self.id.clock + offset,
+        }
+    }
+
+    /// The length of the first `chars` chars
+    fn prefix(&self, chars: usize) -> TextLen {
+        TextLen::of_str(&self.text[..self.byte_offset(chars)])
+    }
+
+    /// The number of chars before `offset`, or `None` if it's inside a char
+    fn char_offset(&self, offset: usize, unit: Unit) -> Option<usize> {
+        let mut len = TextLen::default();
+        for c in self.text.chars() {
+            if len.get(unit) >= offset {
+                break;
+            }
+            len = len + TextLen::of(c);
+        }
+
+        (len.get(unit) == offset).then_some(len.chars)
+    }
+
+    fn byte_offset(&self, chars: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(chars)
+            .map(|(i, _)| i)
+            .unwrap_or(self.text.len())
+    }
+
+    /// Split the chunk after `chars` chars, it returns the second half
+    fn split_off(&mut self, chars: usize) -> Chunk {
+        let text = self.text.split_off(self.byte_offset(chars));
+        Chunk {
+            id: self.id_at(chars),
+            text,
+        }
+    }
+}
+
+/// A text of chars, see the [module docs](self) for how it's stored
+#[derive(Debug, Clone)]
+pub struct TextDoc<A> {
+    doc: ListDoc<A, char>,
+    index: IndexTree<Chunk, TextLen>,
+    /// the node of every chunk by its first id
+    chunks: BTreeMap<OpId, NodeId>,
+}
+
+impl<A: Algorithm> TextDoc<A> {
+    pub fn new(client_id: PeerId) -> Self {
+        TextDoc {
+            doc: ListDoc::new(client_id),
+            index: IndexTree::default(),
+            chunks: BTreeMap::new(),
+        }
+    }
+
+    pub fn doc(&self) -> &ListDoc<A, char> {
+        &self.doc
+    }
+
+    pub fn len(&self, unit: Unit) -> usize {
+        self.index.total().get(unit)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.len() == 0
+    }
+
+    /// Convert `pos` from one unit to another.
+    ///
+    /// # Panics
+    ///
+    /// If `pos` is out of range or not on a char boundary
+    pub fn convert(&self, pos: usize, from: Unit, to: Unit) -> usize {
+        self.pos(self.char_index(pos, from), to)
+    }
+
+    /// The id of the op that inserted the char at `pos`
+    pub fn id_at(&self, pos: usize, unit: Unit) -> Option<OpId> {
+        let (index, offset) = self.index.find(self.char_index(pos, unit), |x| x.chars);
+        let node = self.index.node_at(index)?;
+        Some(self.index.get(node).id_at(offset))
+    }
+
+    /// The position of the char inserted by `id`, or `None` if it's deleted or unknown
+    pub fn pos_of(&self, id: OpId, unit: Unit) -> Option<usize> {
+        let (start, &node) = self.chunks.range(..=id).next_back()?;
+        let offset = id.clock - start.clock;
+        if start.client_id != id.client_id || offset >= self.index.metric(node).chars {
+            return None;
+        }
+
+        let before = self.index.prefix(self.index.index_of(node));
+        Some(before.get(unit) + self.index.get(node).prefix(offset).get(unit))
+    }
+
+    /// It returns one op per char of `text`
+    pub fn insert(&mut self, pos: usize, unit: Unit, text: &str) -> Vec<ListOp<char>> {
+        let index = self.char_index(pos, unit);
+        let ops = self.doc.insert(index, text.chars());
+        if let Some(first) = ops.first() {
+            let chars: Vec<char> = text.chars().collect();
+            for (i, run) in chars.chunks(MAX_CHUNK).enumerate() {
+                let offset = i * MAX_CHUNK;
+                let chunk = Chunk {
+                    id: OpId {
+                        client_id: first.id().client_id,
+                        clock: first.id().clock + offset,
+                    },
+                    text: run.iter().collect(),
+                };
+                self.insert_chunk(index + offset, chunk);
+            }
+        }
+
+        ops
+    }
+
+    pub fn delete(&mut self, range: Range<usize>, unit: Unit) -> Vec<ListOp<char>> {
+        let start = self.char_index(range.start, unit);
+        let end = self.char_index(range.end, unit);
+        let ops = self.doc.delete(start..end);
+        self.remove_chars(start..end);
+        ops
+    }
+
+    /// Apply an op from another replica, the returned events use positions in `unit`
+    pub fn apply_remote(&mut self, op: ListOp<char>, unit: Unit) -> Vec<TextEvent> {
+        let mut ans = Vec::new();
+        for event in self.doc.apply_remote(op) {
+            match event {
+                Event::Insert { index, id, value } => {
+                    let text = value.to_string();
+                    self.insert_chunk(
+                        index,
+                        Chunk {
+                            id,
+                            text: text.clone(),
+                        },
+                    );
+                    ans.push(TextEvent::Insert {
+                        pos: self.pos(index, unit),
+                        text,
+                    });
+                }
+                Event::Delete { index, id } => {
+                    debug_assert_eq!(self.id_at(index, Unit::Char), Some(id));
+                    let len = self.remove_chars(index..index + 1);
+                    ans.push(TextEvent::Delete {
+                        pos: self.pos(index, unit),
+                        len: len.get(unit),
+                    });
+                }
+            }
+        }
+
+        ans
+    }
+
+    /// The number of chars before `pos`
+    fn char_index(&self, pos: usize, unit: Unit) -> usize {
+        assert!(pos <= self.len(unit), "pos out of range");
+        let (index, offset) = self.index.find(pos, |x| x.get(unit));
+        let Some(node) = self.index.node_at(index) else {
+            return self.index.total().chars;
+        };
+
+        let chars = self.index.get(node).char_offset(offset, unit);
+        let chars = chars.expect("pos is not on a char boundary");
+        self.index.prefix(index).chars + chars
+    }
+
+    /// The position in `unit` of the `index`-th char
+    fn pos(&self, index: usize, unit: Unit) -> usize {
+        let (node_index, offset) = self.index.find(index, |x| x.chars);
+        let before = self.index.prefix(node_index).get(unit);
+        match self.index.node_at(node_index) {
+            Some(node) => before + self.index.get(node).prefix(offset).get(unit),
+            None => before,
+        }
+    }
+
+    /// Make the `index`-th char the start of a chunk, it returns the index of that chunk
+    fn split(&mut self, index: usize) -> usize {
+        let (node_index, offset) = self.index.find(index, |x| x.chars);
+        if offset == 0 {
+            return node_index;
+        }
+
+        let node = self.index.node_at(node_index).unwrap();
+        let tail = self.index.get_mut(node).split_off(offset);
+        let len = TextLen::of_str(&tail.text);
+        self.index.set_metric(node, self.index.metric(node) - len);
+        let id = tail.id;
+        let tail = self.index.insert(node_index + 1, tail, len);
+        self.chunks.insert(id, tail);
+        node_index + 1
+    }
+
+    /// Insert `chunk` before the `index`-th char, appending it to the previous chunk if
+    /// it continues it
+    fn insert_chunk(&mut self, index: usize, chunk: Chunk) {
+        let node_index = self.split(index);
+        let len = TextLen::of_str(&chunk.text);
+        if let Some(prev) = node_index
+            .checked_sub(1)
+            .and_then(|i| self.index.node_at(i))
+        {
+            let prev_len = self.index.metric(prev);
+            let continues = self.index.get(prev).id_at(prev_len.chars) == chunk.id;
+            if continues && prev_len.chars + len.chars <= MAX_CHUNK {
+                self.index.get_mut(prev).text.push_str(&chunk.text);
+                self.index.set_metric(prev, prev_len + len);
+                return;
+            }
+        }
+
+        let id = chunk.id;
+        let node = self.index.insert(node_index, chunk, len);
+        self.chunks.insert(id, node);
+    }
+
+    /// Remove the chars in `range`, it returns their length
+    fn remove_chars(&mut self, range: Range<usize>) -> TextLen {
+        let start = self.split(range.start);
+        let end = self.split(range.end);
+        let mut len = TextLen::default();
+        for _ in start..end {
+            let node = self.index.node_at(start).unwrap();
+            len = len + self.index.metric(node);
+            let chunk = self.index.remove(node);
+            self.chunks.remove(&chunk.id);
+        }
+
+        len
+    }
+}
+
+impl<A: Algorithm> Display for TextDoc<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.index
+            .iter()
+            .try_for_each(|chunk| write!(f, "{}", chunk.text))
+    }
+}
+
+#[cfg(test)]
+mod text_test {
+    use super::*;
+    use crate::list_doc::{Fugue, Yata};
+
+    #[test]
+    fn convert() {
+        let mut text = TextDoc::<Fugue>::new(0);
+        text.insert(0, Unit::Utf8, "a😀é");
+        text.insert(5, Unit::Utf8, "b");
+        assert_eq!(text.to_string(), "a😀bé");
+        assert_eq!(text.len(Unit::Utf8), 8);
+        assert_eq!(text.len(Unit::Utf16), 5);
+        assert_eq!(text.len(Unit::Char), 4);
+        assert_eq!(text.convert(5, Unit::Utf8, Unit::Utf16), 3);
+        assert_eq!(text.convert(3, Unit::Utf16, Unit::Char), 2);
+        assert_eq!(text.convert(4, Unit::Char, Unit::Utf8), 8);
+
+        let id = text.id_at(1, Unit::Utf16).unwrap();
+        assert_eq!(text.pos_of(id, Unit::Utf8), Some(1));
+        text.delete(1..3, Unit::Utf16);
+        assert_eq!(text.to_string(), "abé");
+        assert_eq!(text.pos_of(id, Unit::Utf8), None);
+    }
+
+    #[test]
+    fn chunks() {
+        let mut text = TextDoc::<Fugue>::new(0);
+        let mut ops = text.insert(0, Unit::Char, "hello");
+        ops.extend(text.insert(5, Unit::Char, " world"));
+        // typing continues the chunk
+        assert_eq!(text.index.len(), 1);
+
+        let mid = text.id_at(2, Unit::Char).unwrap();
+        ops.extend(text.insert(2, Unit::Char, "ü"));
+        assert_eq!(text.index.len(), 3);
+        ops.extend(text.delete(8..9, Unit::Utf8));
+        assert_eq!(text.index.len(), 4);
+        assert_eq!(text.to_string(), "heüllo orld");
+        assert_eq!(text.pos_of(mid, Unit::Utf8), Some(4));
+        assert_eq!(text.id_at(4, Unit::Utf8), Some(mid));
+
+        // a remote replica builds the same chunks from the ops of single chars
+        let mut remote = TextDoc::<Fugue>::new(1);
+        for op in ops {
+            remote.apply_remote(op, Unit::Char);
+        }
+        assert_eq!(remote.to_string(), text.to_string());
+        assert_eq!(remote.index.len(), 4);
+
+        let long: String = "ab".repeat(MAX_CHUNK);
+        text.insert(0, Unit::Char, &long);
+        assert_eq!(text.index.len(), 6);
+        assert_eq!(text.len(Unit::Char), 2 * MAX_CHUNK + 11);
+    }
+
+    #[test]
+    #[should_panic]
+    fn inside_surrogate_pair() {
+        let mut text = TextDoc::<Fugue>::new(0);
+        text.insert(0, Unit::Char, "😀");
+        text.convert(1, Unit::Utf16, Unit::Utf8);
+    }
+
+    /// A UTF-16 client applies the remote events to its own buffer
+    #[test]
+    fn remote_events_in_utf16() {
+        let mut server = TextDoc::<Yata>::new(0);
+        let mut client = TextDoc::<Yata>::new(1);
+        let mut buffer: Vec<u16> = Vec::new();
+        let mut ops = server.insert(0, Unit::Utf8, "héllo 😀 world");
+        ops.extend(server.delete(1..3, Unit::Utf8));
+        ops.extend(server.insert(5, Unit::Utf8, "🎉"));
+        ops.extend(server.insert(0, Unit::Utf8, "ü"));
+        ops.extend(server.delete(7..11, Unit::Utf8));
+
+        for op in ops {
+            for event in client.apply_remote(op, Unit::Utf16) {
+                match event {
+                    TextEvent::Insert { pos, text } => {
+                        buffer.splice(pos..pos, text.encode_utf16());
+                    }
+                    TextEvent::Delete { pos, len } => {
+                        buffer.drain(pos..pos + len);
+                    }
+                }
+            }
+        }
+
+        let expected: String = client.to_string();
+        assert_eq!(expected, "ühllo 😀 world");
+        assert_eq!(expected, server.to_string());
+        assert_eq!(String::from_utf16(&buffer).unwrap(), expected);
+        assert_eq!(client.len(Unit::Utf16), buffer.len());
+    }
+}
@@ -0,0 +1,104 @@
+//! Policies that order the concurrent inserts at the same position.
+//!
+//! The algorithms only need a total order on the client ids that every replica agrees on,
+//! which is compared by [crate::crdt::ListCrdt::cmp_id]. The implementations in this crate
+//! take the policy as a type parameter, which defaults to [Ascending].
+
+use std::{cmp::Ordering, fmt::Debug};
+
+/// The policies are marker types, like the algorithms of [crate::list_doc::ListDoc]
+pub trait TieBreak<ClientId>: Debug + Clone {
+    fn cmp_client(a: &ClientId, b: &ClientId) -> Ordering;
+}
+
+/// Compare the client ids
+#[derive(Debug, Clone, Copy)]
+pub enum Ascending {}
+
+/// Compare the client ids in reverse
+#[derive(Debug, Clone, Copy)]
+pub enum Descending {}
+
+/// The client `ID`, e.g. a server, is treated as the greatest client.
+/// The others are compared by their ids. `ID` is a full 128-bit peer id, so it can name
+/// one of the random ids of [crate::peer::PeerId].
+#[derive(Debug, Clone, Copy)]
+pub enum Priority<const ID: u128> {}
+
+/// A random-looking order that is stable across replicas and versions, so no peer
+/// is always favored. The ids are mixed with SplitMix64 before they are compared.
+#[derive(Debug, Clone, Copy)]
+pub enum Hashed {}
+
+impl<C: Ord> TieBreak<C> for Ascending {
+    fn cmp_client(a: &C, b: &C) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+impl<C: Ord> TieBreak<C> for Descending {
+    fn cmp_client(a: &C, b: &C) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+impl<const ID: u128> TieBreak<u128> for Priority<ID> {
+    fn cmp_client(a: &u128, b: &u128) -> Ordering {
+        (*a == ID).cmp(&(*b == ID)).then(a.cmp(b))
+    }
+}
+
+impl<const ID: u128> TieBreak<usize> for Priority<ID> {
+    fn cmp_client(a: &usize, b: &usize) -> Ordering {
+        <Priority<ID> as TieBreak<u128>>::cmp_client(&(*a as u128), &(*b as u128))
+    }
+}
+
+pub(crate) const fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl TieBreak<u128> for Hashed {
+    fn cmp_client(a: &u128, b: &u128) -> Ordering {
+        let hash = |x: u128| (mix(x as u64 ^ mix((x >> 64) as u64)), x);
+        hash(*a).cmp(&hash(*b))
+    }
+}
+
+impl TieBreak<usize> for Hashed {
+    fn cmp_client(a: &usize, b: &usize) -> Ordering {
+        <Hashed as TieBreak<u128>>::cmp_client(&(*a as u128), &(*b as u128))
+    }
+}
+
+#[cfg(test)]
+mod tie_break_test {
+    use super::*;
+
+    fn sorted<P: TieBreak<usize>>() -> Vec<usize> {
+        let mut ids: Vec<usize> = (0..6).collect();
+        ids.sort_by(P::cmp_client);
+        ids
+    }
+
+    #[test]
+    fn policies_are_total_orders() {
+        assert_eq!(sorted::<Ascending>(), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(sorted::<Descending>(), vec![5, 4, 3, 2, 1, 0]);
+        assert_eq!(sorted::<Priority<2>>(), vec![0, 1, 3, 4, 5, 2]);
+        let big = 1u128 << 100;
+        let mut ids = vec![u128::MAX, big, 7];
+        ids.sort_by(<Priority<{ 1 << 100 }> as TieBreak<u128>>::cmp_client);
+        assert_eq!(ids, vec![7, u128::MAX, big]);
+        let hashed = sorted::<Hashed>();
+        assert_ne!(hashed, sorted::<Ascending>());
+        assert_eq!(hashed, sorted::<Hashed>());
+        assert_eq!(
+            <Hashed as TieBreak<u128>>::cmp_client(&(1 << 100), &(1 << 100)),
+            Ordering::Equal
+        );
+    }
+}
@@ -8,56 +8,185 @@ pub trait Woot: ListCrdt {
     fn insert_at(container: &mut Self::Container, op: Self::OpUnit, pos: usize);
 }
 
+/// WOOTO annotates every op with a degree when it's created:
+/// `max(degree(left), degree(right)) + 1`, where the document boundaries have degree 0
+pub trait WootO: Woot {
+    fn degree(op: &Self::OpUnit) -> usize;
+}
+
+/// WOOTH is WOOTO over a container that can access ops by position directly,
+/// and find the position of an op by its id via a hash index
+pub trait WootH: WootO {
+    fn op_at(container: &Self::Container, pos: usize) -> &Self::OpUnit;
+}
+
+/// The original algorithm recurses into the narrowed `(prev, next)` window.
+/// It's written as a loop here, so the stack usage doesn't grow with the number of concurrent ops.
 pub fn integrate<T: Woot>(
     container: &mut T::Container,
     to_insert: T::OpUnit,
-    left: Option<T::OpId>,
-    right: Option<T::OpId>,
+    mut left: Option<T::OpId>,
+    mut right: Option<T::OpId>,
 ) {
-    let mut set = T::Set::default();
-    let mut empty_between_left_and_right = true;
-    for ref op in T::iter(container, left, right) {
-        let op = &op.get_op();
-        if (left.is_some() && T::contains(op, left.unwrap()))
-            || (right.is_some() && T::contains(op, right.unwrap()))
-        {
-            continue;
+    if T::integrated(container, T::id(&to_insert)) {
+        return;
+    }
+
+    loop {
+        let mut set = T::Set::default();
+        let mut empty_between_left_and_right = true;
+        for ref op in T::iter(container, left, right) {
+            let op = op.get_op_ref();
+            if (left.is_some() && T::contains(op, left.unwrap()))
+                || (right.is_some() && T::contains(op, right.unwrap()))
+            {
+                continue;
+            }
+
+            empty_between_left_and_right = false;
+            set.insert(op);
         }
 
-        empty_between_left_and_right = false;
-        set.insert(op);
-    }
+        if empty_between_left_and_right {
+            insert_before::<T>(container, to_insert, right);
+            return;
+        }
 
-    if empty_between_left_and_right {
-        match right {
-            Some(right) => T::insert_at(container, to_insert, T::get_pos_of(container, right)),
-            None => T::insert_at(container, to_insert, T::len(container)),
+        let mut prev = left;
+        let mut next = right;
+        for ref iter_op in T::iter(container, left, right).filter(|op| {
+            let op = op.get_op_ref();
+            let left = T::left(op);
+            let right = T::right(op);
+            (left.is_none() || !set.contain(left.unwrap()))
+                && (right.is_none() || !set.contain(right.unwrap()))
+        }) {
+            let iter_op = iter_op.get_op_ref();
+            if Some(T::id(iter_op)) == left || Some(T::id(iter_op)) == right {
+                // left cannot be next, and right cannot be prev
+                continue;
+            }
+
+            if T::cmp_id(iter_op, &to_insert).is_lt() {
+                prev = Some(T::id(iter_op));
+            } else {
+                next = Some(T::id(iter_op));
+                break;
+            }
         }
+
+        left = prev;
+        right = next;
+    }
+}
+
+fn insert_before<T: Woot>(container: &mut T::Container, op: T::OpUnit, right: Option<T::OpId>) {
+    match right {
+        Some(right) => T::insert_at(container, op, T::get_pos_of(container, right)),
+        None => T::insert_at(container, op, T::len(container)),
+    }
+}
+
+/// WOOTO only needs to look at the ops with the minimal degree between `left` and `right`,
+/// so it doesn't need to build the set of ops in between
+pub fn integrate_o<T: WootO>(
+    container: &mut T::Container,
+    to_insert: T::OpUnit,
+    mut left: Option<T::OpId>,
+    mut right: Option<T::OpId>,
+) {
+    if T::integrated(container, T::id(&to_insert)) {
         return;
     }
 
-    let mut prev = left;
-    let mut next = right;
-    for ref iter_op in T::iter(container, left, right).filter(|op| {
-        let op = &op.get_op();
-        let left = T::left(op);
-        let right = T::right(op);
-        (left.is_none() || !set.contain(left.unwrap()))
-            && (right.is_none() || !set.contain(right.unwrap()))
-    }) {
-        let iter_op = &iter_op.get_op();
-        if Some(T::id(iter_op)) == left || Some(T::id(iter_op)) == right {
-            // left cannot be next, and right cannot be prev
-            continue;
+    loop {
+        let is_bound = |op: &T::OpUnit| {
+            (left.is_some() && T::contains(op, left.unwrap()))
+                || (right.is_some() && T::contains(op, right.unwrap()))
+        };
+
+        let mut min_degree = None;
+        for ref op in T::iter(container, left, right) {
+            let op = op.get_op_ref();
+            if is_bound(op) {
+                continue;
+            }
+
+            let degree = T::degree(op);
+            if min_degree.map(|x| degree < x).unwrap_or(true) {
+                min_degree = Some(degree);
+            }
         }
 
-        if T::cmp_id(iter_op, &to_insert).is_lt() {
-            prev = Some(T::id(iter_op));
-        } else {
-            next = Some(T::id(iter_op));
-            break;
+        let Some(min_degree) = min_degree else {
+            insert_before::<T>(container, to_insert, right);
+            return;
+        };
+
+        let mut prev = left;
+        let mut next = right;
+        for ref iter_op in T::iter(container, left, right) {
+            let iter_op = iter_op.get_op_ref();
+            if is_bound(iter_op) || T::degree(iter_op) != min_degree {
+                continue;
+            }
+
+            if T::cmp_id(iter_op, &to_insert).is_lt() {
+                prev = Some(T::id(iter_op));
+            } else {
+                next = Some(T::id(iter_op));
+                break;
+            }
         }
+
+        left = prev;
+        right = next;
     }
+}
 
-    integrate::<T>(container, to_insert, prev, next);
+/// Same as [integrate_o], but it jumps to the positions of `left` and `right` directly
+/// instead of scanning the container from the start
+pub fn integrate_h<T: WootH>(
+    container: &mut T::Container,
+    to_insert: T::OpUnit,
+    mut left: Option<T::OpId>,
+    mut right: Option<T::OpId>,
+) {
+    if T::integrated(container, T::id(&to_insert)) {
+        return;
+    }
+
+    loop {
+        let start = left.map(|x| T::get_pos_of(container, x) + 1).unwrap_or(0);
+        let end = right
+            .map(|x| T::get_pos_of(container, x))
+            .unwrap_or_else(|| T::len(container));
+        if start == end {
+            T::insert_at(container, to_insert, end);
+            return;
+        }
+
+        let min_degree = (start..end)
+            .map(|i| T::degree(T::op_at(container, i)))
+            .min()
+            .unwrap();
+        let mut prev = left;
+        let mut next = right;
+        for i in start..end {
+            let iter_op = T::op_at(container, i);
+            if T::degree(iter_op) != min_degree {
+                continue;
+            }
+
+            if T::cmp_id(iter_op, &to_insert).is_lt() {
+                prev = Some(T::id(iter_op));
+            } else {
+                next = Some(T::id(iter_op));
+                break;
+            }
+        }
+
+        left = prev;
+        right = next;
+    }
 }
@@ -1,36 +1,30 @@
-use std::collections::HashSet;
+use std::marker::PhantomData;
 
-pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl};
-use crate::{crdt::ListCrdt, test::TestFramework, woot};
+use crate::dumb_common::peer_id;
+pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl, PeerId};
+use crate::id_span::IdSpanSet;
+use crate::tie_break::{Ascending, TieBreak};
+use crate::{crdt::ListCrdt, local::Tombstones, test::TestFramework, woot};
 
-impl WootImpl {
+impl<P: TieBreak<PeerId>> WootImpl<P> {
     fn container_contains(
         container: &<Self as ListCrdt>::Container,
         op_id: Option<<Self as ListCrdt>::OpId>,
     ) -> bool {
-        if op_id.is_none() {
-            return true;
-        }
-
-        let op_id = op_id.unwrap();
-        container.content.iter().any(|x| x.id == op_id)
-
-        // if container.version_vector.len() <= op_id.client_id {
-        //     return false;
-        // }
-
-        // container.version_vector[op_id.client_id] > op_id.clock
+        container.contains(op_id)
     }
 }
 
-pub struct WootImpl;
-impl ListCrdt for WootImpl {
+pub struct WootImpl<P = Ascending>(PhantomData<P>);
+impl<P: TieBreak<PeerId>> ListCrdt for WootImpl<P> {
     type OpUnit = Op;
 
     type OpId = OpId;
 
     type Container = Container;
 
+    type Pos = usize;
+
     type Cursor<'a> = Cursor<'a>;
 
     type Set = OpSetImpl;
@@ -38,12 +32,12 @@ impl ListCrdt for WootImpl {
     type Iterator<'a> = Iter<'a>;
 
     fn iter(
-        container: &mut Self::Container,
+        container: &Self::Container,
         from: Option<Self::OpId>,
         to: Option<Self::OpId>,
     ) -> Self::Iterator<'_> {
         Iter {
-            arr: &mut container.content,
+            arr: &container.content,
             index: 0,
             start: from,
             end: to,
@@ -58,18 +52,34 @@ impl ListCrdt for WootImpl {
     }
 
     fn cmp_id(op_a: &Self::OpUnit, op_b: &Self::OpUnit) -> std::cmp::Ordering {
-        op_a.id
-            .client_id
-            .cmp(&op_b.id.client_id)
+        P::cmp_client(&op_a.id.client_id, &op_b.id.client_id)
             .then(op_a.id.clock.cmp(&op_b.id.clock))
     }
 
     fn contains(op: &Self::OpUnit, id: Self::OpId) -> bool {
         op.id == id
     }
+
+    fn integrated(container: &Self::Container, id: Self::OpId) -> bool {
+        container.contains(Some(id))
+    }
 }
 
-impl woot::Woot for WootImpl {
+impl<P: TieBreak<PeerId>> Tombstones for WootImpl<P> {
+    fn get(container: &Self::Container, id: OpId) -> Option<&Op> {
+        container.content.get(id)
+    }
+
+    fn visible_id(container: &Self::Container, index: usize) -> Option<OpId> {
+        container.content.visible_id(index)
+    }
+
+    fn next_id(container: &Self::Container, id: Option<OpId>) -> Option<OpId> {
+        container.content.next_id(id)
+    }
+}
+
+impl<P: TieBreak<PeerId>> woot::Woot for WootImpl<P> {
     fn len(container: &Self::Container) -> usize {
         container.content.len()
     }
@@ -83,11 +93,7 @@ impl woot::Woot for WootImpl {
     }
 
     fn get_pos_of(container: &Container, op_id: Self::OpId) -> usize {
-        container
-            .content
-            .iter()
-            .position(|x| x.id == op_id)
-            .unwrap()
+        container.content.pos_of(op_id).unwrap()
     }
 
     fn insert_at(container: &mut Self::Container, op: Self::OpUnit, pos: usize) {
@@ -95,17 +101,29 @@ impl woot::Woot for WootImpl {
     }
 }
 
-impl TestFramework for WootImpl {
+impl<P: TieBreak<PeerId>> woot::WootO for WootImpl<P> {
+    fn degree(op: &Self::OpUnit) -> usize {
+        op.degree
+    }
+}
+
+impl<P: TieBreak<PeerId>> woot::WootH for WootImpl<P> {
+    fn op_at(container: &Self::Container, pos: usize) -> &Self::OpUnit {
+        &container.content[pos]
+    }
+}
+
+impl<P: TieBreak<PeerId>> TestFramework for WootImpl<P> {
     fn is_content_eq(a: &Self::Container, b: &Self::Container) -> bool {
         a.content.eq(&b.content)
     }
 
+    fn fork(container: &Self::Container, id: usize) -> Self::Container {
+        container.fork(peer_id(id))
+    }
+
     fn new_container(id: usize) -> Self::Container {
-        Container {
-            id,
-            version_vector: vec![0; 10],
-            ..Default::default()
-        }
+        Container::new(peer_id(id))
     }
 
     fn new_op(container: &mut Self::Container, pos: usize) -> Self::OpUnit {
@@ -123,6 +141,10 @@ impl TestFramework for WootImpl {
             )
         };
 
+        let degree_of = |id: Option<OpId>| {
+            id.map(|id| container.content[container.content.pos_of(id).unwrap()].degree)
+                .unwrap_or(0)
+        };
         let ans = Op {
             id: OpId {
                 client_id: container.id,
@@ -130,6 +152,7 @@ impl TestFramework for WootImpl {
             },
             left,
             right,
+            degree: std::cmp::max(degree_of(left), degree_of(right)) + 1,
             deleted: false,
             lamport: 0,
         };
@@ -138,41 +161,40 @@ impl TestFramework for WootImpl {
         ans
     }
 
-    type DeleteOp = HashSet<Self::OpId>;
+    type DeleteOp = IdSpanSet<PeerId>;
 
     fn new_del_op(container: &Self::Container, mut pos: usize, mut len: usize) -> Self::DeleteOp {
         let content_len = container.content.real_len();
-        let mut deleted = HashSet::new();
+        let mut deleted = IdSpanSet::new();
         if content_len == 0 {
             return deleted;
         }
 
         pos %= content_len;
         len = std::cmp::min(len, content_len - pos);
-        for op in container.content.iter_real().skip(pos).take(len) {
-            deleted.insert(op.id);
+        for op in container.content.iter_real_from(pos).take(len) {
+            deleted.insert(op.id.client_id, op.id.clock);
         }
 
         deleted
     }
 
     fn integrate_delete_op(container: &mut Self::Container, delete_set: Self::DeleteOp) {
-        for op in container.content.iter_real_mut() {
-            if delete_set.contains(&op.id) {
-                op.deleted = true;
-            }
-        }
+        container.content.delete(&delete_set);
+        container.deleted = container.deleted.union(&delete_set);
+    }
+
+    fn can_integrate_delete_op(container: &Self::Container, op: &Self::DeleteOp) -> bool {
+        container.contains_all(op)
     }
 
     fn integrate(container: &mut Self::Container, op: Self::OpUnit) {
-        let id = Self::id(&op);
-        for _ in container.version_vector.len()..id.client_id + 1 {
-            container.version_vector.push(0);
+        if container.contains(Some(op.id)) {
+            return;
         }
-        assert!(container.version_vector[id.client_id] == id.clock);
-        woot::integrate::<WootImpl>(container, op.clone(), op.left, op.right);
 
-        container.version_vector[id.client_id] = id.clock + 1;
+        woot::integrate::<Self>(container, op.clone(), op.left, op.right);
+        container.advance(&op);
     }
 
     fn can_integrate(container: &Self::Container, op: &Self::OpUnit) -> bool {
@@ -192,6 +214,7 @@ impl TestFramework for WootImpl {
 #[cfg(test)]
 mod woot_impl_test {
     use super::*;
+    use crate::woot::integrate;
 
     #[test]
     fn run() {
@@ -214,6 +237,202 @@ mod woot_impl_test {
         }
     }
 
+    /// Every replica keeps one container per variant, and every op is integrated into all of them.
+    /// Replicas receive ops in the order they were created, but only catch up at random moments,
+    /// so there are plenty of concurrent ops.
+    #[test]
+    fn variants_have_the_same_order() {
+        use crate::woot::{integrate_h, integrate_o};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        for seed in 0..100 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let n = 3;
+            let mut replicas: Vec<[Container; 3]> = (0..n)
+                .map(|i| std::array::from_fn(|_| <WootImpl>::new_container(i)))
+                .collect();
+            let mut delivered = vec![0; n];
+            let mut log: Vec<Op> = Vec::new();
+            for _ in 0..200 {
+                let i = rng.gen_range(0..n);
+                if rng.gen_bool(0.3) {
+                    let to = rng.gen_range(delivered[i]..=log.len());
+                    for op in log[delivered[i]..to].iter() {
+                        if op.id.client_id == peer_id(i) {
+                            continue;
+                        }
+
+                        let [a, b, c] = &mut replicas[i];
+                        integrate::<WootImpl>(a, op.clone(), op.left, op.right);
+                        integrate_o::<WootImpl>(b, op.clone(), op.left, op.right);
+                        integrate_h::<WootImpl>(c, op.clone(), op.left, op.right);
+                    }
+                    delivered[i] = to;
+                } else {
+                    let [a, b, c] = &mut replicas[i];
+                    let op = <WootImpl>::new_op(a, rng.gen());
+                    b.max_clock += 1;
+                    c.max_clock += 1;
+                    integrate::<WootImpl>(a, op.clone(), op.left, op.right);
+                    integrate_o::<WootImpl>(b, op.clone(), op.left, op.right);
+                    integrate_h::<WootImpl>(c, op.clone(), op.left, op.right);
+                    log.push(op);
+                }
+
+                let [a, b, c] = &replicas[i];
+                assert_eq!(a.content, b.content);
+                assert_eq!(a.content, c.content);
+            }
+        }
+    }
+
+    /// Client 0 types a long run of text while 500 other clients insert at the start concurrently.
+    /// The first of them has to walk down the whole run, which used to recurse once per op.
+    #[test]
+    fn deep_concurrent_inserts_with_small_stack() {
+        use crate::woot::{integrate_h, integrate_o};
+
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| {
+                let mut typing = <WootImpl>::new_container(0);
+                let mut run = Vec::new();
+                for i in 0..1500 {
+                    let op = <WootImpl>::new_op(&mut typing, i);
+                    <WootImpl>::integrate(&mut typing, op.clone());
+                    run.push(op);
+                }
+
+                let concurrent: Vec<Op> = (1..=500)
+                    .map(|client_id| {
+                        <WootImpl>::new_op(&mut <WootImpl>::new_container(client_id), 0)
+                    })
+                    .collect();
+
+                let mut results = Vec::new();
+                for ops in [
+                    run.iter().chain(concurrent.iter()),
+                    concurrent.iter().chain(run.iter()),
+                ] {
+                    let mut a = <WootImpl>::new_container(0);
+                    let mut b = <WootImpl>::new_container(0);
+                    let mut c = <WootImpl>::new_container(0);
+                    for op in ops {
+                        integrate::<WootImpl>(&mut a, op.clone(), op.left, op.right);
+                        integrate_o::<WootImpl>(&mut b, op.clone(), op.left, op.right);
+                        integrate_h::<WootImpl>(&mut c, op.clone(), op.left, op.right);
+                    }
+
+                    assert_eq!(a.content.len(), 2000);
+                    assert_eq!(a.content, b.content);
+                    assert_eq!(a.content, c.content);
+                    results.push(a.content);
+                }
+
+                assert_eq!(results[0], results[1]);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn fork_merge() {
+        for seed in 0..100 {
+            crate::test::test_fork_merge::<WootImpl>(seed, 3, 50);
+        }
+    }
+
+    #[test]
+    fn exhaustive() {
+        crate::test::explore::<WootImpl>(2, 4, 2);
+        crate::test::explore::<WootImpl>(3, 3, 2);
+    }
+
+    #[test]
+    fn delivery_orders() {
+        for seed in 0..20 {
+            crate::test::test_delivery_orders::<WootImpl>(seed, 3, 200, 10);
+        }
+    }
+
+    #[test]
+    fn lossy_network() {
+        for seed in 0..10 {
+            crate::network::test_network::<WootImpl>(seed, 4, 200, Default::default());
+        }
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        for seed in 0..20 {
+            crate::test::test_duplicates::<WootImpl>(seed, 3, 300);
+        }
+    }
+
+    #[test]
+    fn local_ops_at_visible_index() {
+        let mut container = <WootImpl>::new_container(0);
+        for i in 0..10 {
+            let op = <WootImpl>::new_op(&mut container, i * 3);
+            <WootImpl>::integrate(&mut container, op);
+        }
+        let deleted = <WootImpl>::new_del_op(&container, 2, 4);
+        <WootImpl>::integrate_delete_op(&mut container, deleted);
+
+        let ids: Vec<OpId> = (10..13)
+            .map(|clock| OpId {
+                client_id: container.id,
+                clock,
+            })
+            .collect();
+        let origins = crate::local::insert_origins::<WootImpl>(&container, 2, &ids);
+        let degrees = crate::local::woot_degrees::<WootImpl>(&container, &origins);
+        // the right origin is the first tombstone
+        assert_eq!(origins[0].right, Some(container.content[2].id));
+        assert_eq!(origins[2].left, Some(ids[1]));
+        for ((id, origins), degree) in ids.iter().zip(origins).zip(degrees) {
+            let op = Op {
+                id: *id,
+                lamport: 0,
+                left: origins.left,
+                right: origins.right,
+                degree,
+                deleted: false,
+            };
+            <WootImpl>::integrate(&mut container, op);
+        }
+
+        let visible: Vec<OpId> = container.content.iter_real().map(|x| x.id).collect();
+        assert_eq!(visible.len(), 9);
+        assert_eq!(&visible[2..5], &ids[..]);
+    }
+
+    #[test]
+    fn tie_break_policies() {
+        use crate::{
+            dumb_common::peer_id,
+            test::tie,
+            tie_break::{Ascending, Descending, Hashed, Priority},
+        };
+        type Server = Priority<{ peer_id(1) }>;
+        // peer 0 has the greater id, but peer 1 wins the tie
+        assert_eq!(
+            tie::<WootImpl<Server>>(0, 1),
+            tie::<WootImpl<Descending>>(0, 1)
+        );
+        assert_ne!(
+            tie::<WootImpl<Server>>(0, 1),
+            tie::<WootImpl<Ascending>>(0, 1)
+        );
+        for seed in 0..30 {
+            crate::test::test::<WootImpl<Descending>>(seed, 3, 300);
+            crate::test::test::<WootImpl<Server>>(seed, 3, 300);
+            crate::test::test::<WootImpl<Hashed>>(seed, 5, 300);
+            crate::test::test_fork_merge::<WootImpl<Hashed>>(seed, 3, 50);
+        }
+    }
+
     use ctor::ctor;
     #[ctor]
     fn init_color_backtrace() {
@@ -3,7 +3,9 @@
 //!
 //!
 
-use crate::crdt::{GetOp, ListCrdt, OpSet};
+use std::{collections::HashMap, hash::Hash};
+
+use crate::crdt::{GetOp, GetPos, ListCrdt, OpSet};
 
 /// For Yata iter should only iterate over the element between `start` and `to`, exclude both `start` and `to`
 pub trait Yata: ListCrdt {
@@ -11,7 +13,12 @@ pub trait Yata: ListCrdt {
     fn left_origin(op: &Self::OpUnit) -> Option<Self::OpId>;
     fn right_origin(op: &Self::OpUnit) -> Option<Self::OpId>;
     /// insert after the anchor
-    fn insert_after(anchor: Self::Cursor<'_>, op: Self::OpUnit, context: &mut Self::Context);
+    fn insert_after(
+        container: &mut Self::Container,
+        anchor: Self::Pos,
+        op: Self::OpUnit,
+        context: &mut Self::Context,
+    );
     fn insert_after_id(
         container: &mut Self::Container,
         id: Option<Self::OpId>,
@@ -25,38 +32,42 @@ pub fn integrate<T: Yata>(
     to_insert: T::OpUnit,
     ctx: &mut T::Context,
 ) {
+    if T::integrated(container, T::id(&to_insert)) {
+        return;
+    }
+
     let this_left_origin = T::left_origin(&to_insert);
     let this_right_origin = T::right_origin(&to_insert);
     let mut cursor = None;
     let mut visited = T::Set::default();
     let mut conflicting_set = T::Set::default();
     for other_cursor in T::iter(container, this_left_origin, this_right_origin) {
-        let other = other_cursor.get_op();
-        if (this_left_origin.is_some() && T::contains(&other, this_left_origin.unwrap()))
-            || (this_right_origin.is_some() && T::contains(&other, this_right_origin.unwrap()))
+        let other = other_cursor.get_op_ref();
+        if (this_left_origin.is_some() && T::contains(other, this_left_origin.unwrap()))
+            || (this_right_origin.is_some() && T::contains(other, this_right_origin.unwrap()))
         {
             unreachable!("For Yata iter should only iterate over the element between `start` and `to`, exclude both `start` and `to`");
         }
 
-        visited.insert(&other);
-        conflicting_set.insert(&other);
-        let other_left_origin = T::left_origin(&other);
+        visited.insert(other);
+        conflicting_set.insert(other);
+        let other_left_origin = T::left_origin(other);
         if other_left_origin == this_left_origin {
-            match T::cmp_id(&to_insert, &other) {
+            match T::cmp_id(&to_insert, other) {
                 std::cmp::Ordering::Less | std::cmp::Ordering::Equal => {
-                    let other_right_origin = T::right_origin(&other);
+                    let other_right_origin = T::right_origin(other);
                     if other_right_origin == this_right_origin {
                         break;
                     }
                 }
                 std::cmp::Ordering::Greater => {
-                    cursor = Some(other_cursor);
+                    cursor = Some(other_cursor.get_pos());
                     conflicting_set.clear();
                 }
             }
         } else if other_left_origin.is_some() && visited.contain(other_left_origin.unwrap()) {
             if !conflicting_set.contain(other_left_origin.unwrap()) {
-                cursor = Some(other_cursor);
+                cursor = Some(other_cursor.get_pos());
                 conflicting_set.clear();
             }
         } else {
@@ -65,10 +76,101 @@ pub fn integrate<T: Yata>(
     }
 
     if let Some(cursor) = cursor {
-        T::insert_after(cursor, to_insert, ctx);
+        T::insert_after(container, cursor, to_insert, ctx);
         return;
     }
 
-    drop(cursor);
     T::insert_after_id(container, this_left_origin, to_insert, ctx);
 }
+
+/// Merge the ops of another replica into `container` in one pass over both lists, e.g. to
+/// merge two replicas. `ops` are all the ops of the other replica, in its list order.
+/// The result is the same as integrating the ops it misses in any causal order.
+///
+/// Both lists have the common ops in the same order, and integrating an op never reorders
+/// the others, so the new ops between two common ops land between them. Where `container`
+/// has no ops of its own between them, the new ops are inserted in their order without a
+/// scan. Otherwise they are concurrent with the ops of `container` there, so they're
+/// integrated one by one at the end, like the ops whose origins aren't inserted yet.
+pub fn merge<T: Yata>(
+    container: &mut T::Container,
+    ops: impl IntoIterator<Item = T::OpUnit>,
+    ctx: &mut T::Context,
+) where
+    T::OpId: Hash,
+{
+    let ops: Vec<T::OpUnit> = ops.into_iter().collect();
+    let common: Vec<T::OpId> = ops
+        .iter()
+        .map(T::id)
+        .filter(|&id| T::integrated(container, id))
+        .collect();
+    // whether `container` has no ops of its own before the `i`-th common op, after the previous one
+    let clean: Vec<bool> = (0..=common.len())
+        .map(|i| {
+            let prev = i.checked_sub(1).map(|i| common[i]);
+            let next = T::iter(container, prev, None)
+                .next()
+                .map(|x| T::id(x.get_op_ref()));
+            next == common.get(i).copied()
+        })
+        .collect();
+
+    let mut inserted = T::Set::default();
+    let mut rest: Vec<T::OpUnit> = Vec::new();
+    let mut gap = 0;
+    let mut last = None;
+    for op in ops {
+        let id = T::id(&op);
+        if T::integrated(container, id) {
+            gap += 1;
+            last = Some(id);
+            continue;
+        }
+
+        // the inserted ops must be closed under the origins, so that their order
+        // is the one integrating them would give
+        let ready = [T::left_origin(&op), T::right_origin(&op)]
+            .into_iter()
+            .flatten()
+            .all(|x| T::integrated(container, x) || inserted.contain(x));
+        if clean[gap] && ready {
+            inserted.insert(&op);
+            T::insert_after_id(container, last, op, ctx);
+            last = Some(id);
+        } else {
+            rest.push(op);
+        }
+    }
+
+    // integrate the rest after their origins
+    let index_of: HashMap<T::OpId, usize> = rest
+        .iter()
+        .enumerate()
+        .map(|(i, op)| (T::id(op), i))
+        .collect();
+    let mut rest: Vec<Option<T::OpUnit>> = rest.into_iter().map(Some).collect();
+    for i in 0..rest.len() {
+        let mut stack = vec![i];
+        while let Some(&j) = stack.last() {
+            let Some(op) = &rest[j] else {
+                stack.pop();
+                continue;
+            };
+
+            let missing = [T::left_origin(op), T::right_origin(op)]
+                .into_iter()
+                .flatten()
+                .filter_map(|x| index_of.get(&x).copied())
+                .find(|&k| rest[k].is_some());
+            match missing {
+                Some(k) => stack.push(k),
+                None => {
+                    let op = rest[j].take().unwrap();
+                    integrate::<T>(container, op, ctx);
+                    stack.pop();
+                }
+            }
+        }
+    }
+}
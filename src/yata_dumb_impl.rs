@@ -1,35 +1,42 @@
-use std::collections::HashSet;
+use std::marker::PhantomData;
 
-pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl};
-use crate::{crdt::ListCrdt, test::TestFramework, yata};
+use crate::dumb_common::peer_id;
+pub use crate::dumb_common::{Container, Cursor, Iter, Op, OpId, OpSetImpl, PeerId};
+use crate::id_span::IdSpanSet;
+use crate::tie_break::{Ascending, TieBreak};
+use crate::{crdt::ListCrdt, local::Tombstones, test::TestFramework, yata};
 
-impl YataImpl {
+impl<P: TieBreak<PeerId>> YataImpl<P> {
     fn container_contains(
         container: &<Self as ListCrdt>::Container,
         op_id: Option<<Self as ListCrdt>::OpId>,
     ) -> bool {
-        if op_id.is_none() {
-            return true;
-        }
-        let op_id = op_id.unwrap();
-        container.content.iter().any(|x| x.id == op_id)
+        container.contains(op_id)
+    }
 
-        // if container.version_vector.len() <= op_id.client_id {
-        //     return false;
-        // }
+    /// Merge `b` into a copy of `a` with [yata::merge]
+    pub fn bulk_merge(a: &Container, b: &Container) -> Container {
+        let mut merged = a.clone();
+        yata::merge::<Self>(&mut merged, b.content.iter().cloned(), &mut ());
+        for op in b.ops_since(a) {
+            merged.advance(op);
+        }
 
-        // container.version_vector[op_id.client_id] > op_id.clock
+        Self::integrate_delete_op(&mut merged, b.deleted.clone());
+        merged
     }
 }
 
-pub struct YataImpl;
-impl ListCrdt for YataImpl {
+pub struct YataImpl<P = Ascending>(PhantomData<P>);
+impl<P: TieBreak<PeerId>> ListCrdt for YataImpl<P> {
     type OpUnit = Op;
 
     type OpId = OpId;
 
     type Container = Container;
 
+    type Pos = usize;
+
     type Cursor<'a> = Cursor<'a>;
 
     type Set = OpSetImpl;
@@ -37,12 +44,12 @@ impl ListCrdt for YataImpl {
     type Iterator<'a> = Iter<'a>;
 
     fn iter(
-        container: &mut Self::Container,
+        container: &Self::Container,
         from: Option<Self::OpId>,
         to: Option<Self::OpId>,
     ) -> Self::Iterator<'_> {
         Iter {
-            arr: &mut container.content,
+            arr: &container.content,
             index: 0,
             start: from,
             end: to,
@@ -57,18 +64,34 @@ impl ListCrdt for YataImpl {
     }
 
     fn cmp_id(op_a: &Self::OpUnit, op_b: &Self::OpUnit) -> std::cmp::Ordering {
-        op_a.id
-            .client_id
-            .cmp(&op_b.id.client_id)
+        P::cmp_client(&op_a.id.client_id, &op_b.id.client_id)
             .then(op_a.id.clock.cmp(&op_b.id.clock))
     }
 
     fn contains(op: &Self::OpUnit, id: Self::OpId) -> bool {
         op.id == id
     }
+
+    fn integrated(container: &Self::Container, id: Self::OpId) -> bool {
+        container.contains(Some(id))
+    }
+}
+
+impl<P: TieBreak<PeerId>> Tombstones for YataImpl<P> {
+    fn get(container: &Self::Container, id: OpId) -> Option<&Op> {
+        container.content.get(id)
+    }
+
+    fn visible_id(container: &Self::Container, index: usize) -> Option<OpId> {
+        container.content.visible_id(index)
+    }
+
+    fn next_id(container: &Self::Container, id: Option<OpId>) -> Option<OpId> {
+        container.content.next_id(id)
+    }
 }
 
-impl yata::Yata for YataImpl {
+impl<P: TieBreak<PeerId>> yata::Yata for YataImpl<P> {
     type Context = ();
     fn left_origin(op: &Self::OpUnit) -> Option<Self::OpId> {
         op.left
@@ -78,12 +101,8 @@ impl yata::Yata for YataImpl {
         op.right
     }
 
-    fn insert_after(anchor: Self::Cursor<'_>, op: Self::OpUnit, _: &mut ()) {
-        if anchor.pos + 1 >= anchor.arr.len() {
-            anchor.arr.push(op);
-        } else {
-            anchor.arr.insert(anchor.pos + 1, op);
-        }
+    fn insert_after(container: &mut Self::Container, anchor: usize, op: Self::OpUnit, _: &mut ()) {
+        container.content.insert(anchor + 1, op);
     }
 
     fn insert_after_id(
@@ -93,7 +112,7 @@ impl yata::Yata for YataImpl {
         _: &mut (),
     ) {
         if let Some(id) = id {
-            let pos = container.content.iter().position(|x| x.id == id).unwrap();
+            let pos = container.content.pos_of(id).unwrap();
             container.content.insert(pos + 1, op);
         } else {
             container.content.insert(0, op);
@@ -101,7 +120,7 @@ impl yata::Yata for YataImpl {
     }
 }
 
-impl TestFramework for YataImpl {
+impl<P: TieBreak<PeerId>> TestFramework for YataImpl<P> {
     fn is_content_eq(a: &Self::Container, b: &Self::Container) -> bool {
         match a.content.eq(&b.content) {
             true => true,
@@ -113,12 +132,12 @@ impl TestFramework for YataImpl {
         }
     }
 
+    fn fork(container: &Self::Container, id: usize) -> Self::Container {
+        container.fork(peer_id(id))
+    }
+
     fn new_container(id: usize) -> Self::Container {
-        Container {
-            id,
-            version_vector: vec![0; 10],
-            ..Default::default()
-        }
+        Container::new(peer_id(id))
     }
 
     fn new_op(container: &mut Self::Container, pos: usize) -> Self::OpUnit {
@@ -143,6 +162,7 @@ impl TestFramework for YataImpl {
             },
             left,
             right,
+            degree: 0,
             deleted: false,
             lamport: 0,
         };
@@ -151,34 +171,33 @@ impl TestFramework for YataImpl {
         ans
     }
 
-    type DeleteOp = HashSet<Self::OpId>;
+    type DeleteOp = IdSpanSet<PeerId>;
 
     fn new_del_op(container: &Self::Container, pos: usize, len: usize) -> Self::DeleteOp {
-        let mut deleted = HashSet::new();
-        for op in container.content.iter_real().skip(pos).take(len) {
-            deleted.insert(op.id);
+        let mut deleted = IdSpanSet::new();
+        for op in container.content.iter_real_from(pos).take(len) {
+            deleted.insert(op.id.client_id, op.id.clock);
         }
 
         deleted
     }
 
     fn integrate_delete_op(container: &mut Self::Container, delete_set: Self::DeleteOp) {
-        for op in container.content.iter_real_mut() {
-            if delete_set.contains(&op.id) {
-                op.deleted = true;
-            }
-        }
+        container.content.delete(&delete_set);
+        container.deleted = container.deleted.union(&delete_set);
+    }
+
+    fn can_integrate_delete_op(container: &Self::Container, op: &Self::DeleteOp) -> bool {
+        container.contains_all(op)
     }
 
     fn integrate(container: &mut Self::Container, op: Self::OpUnit) {
-        let id = Self::id(&op);
-        for _ in container.version_vector.len()..id.client_id + 1 {
-            container.version_vector.push(0);
+        if container.contains(Some(op.id)) {
+            return;
         }
-        assert!(container.version_vector[id.client_id] == id.clock);
-        yata::integrate::<YataImpl>(container, op, &mut ());
 
-        container.version_vector[id.client_id] = id.clock + 1;
+        yata::integrate::<Self>(container, op.clone(), &mut ());
+        container.advance(&op);
     }
 
     fn can_integrate(container: &Self::Container, op: &Self::OpUnit) -> bool {
@@ -220,6 +239,78 @@ mod yata_impl_test {
         }
     }
 
+    #[test]
+    fn fork_merge() {
+        for seed in 0..100 {
+            crate::test::test_fork_merge::<YataImpl>(seed, 3, 50);
+        }
+    }
+
+    #[test]
+    fn bulk_merge() {
+        for seed in 0..100 {
+            crate::test::test_bulk_merge::<YataImpl>(seed, 3, 200, <YataImpl>::bulk_merge);
+            crate::test::test_bulk_merge::<YataImpl<crate::tie_break::Hashed>>(
+                seed,
+                4,
+                200,
+                YataImpl::<crate::tie_break::Hashed>::bulk_merge,
+            );
+        }
+    }
+
+    #[test]
+    fn exhaustive() {
+        crate::test::explore::<YataImpl>(2, 4, 2);
+        crate::test::explore::<YataImpl>(3, 3, 2);
+    }
+
+    #[test]
+    fn delivery_orders() {
+        for seed in 0..20 {
+            crate::test::test_delivery_orders::<YataImpl>(seed, 3, 200, 10);
+        }
+    }
+
+    #[test]
+    fn lossy_network() {
+        for seed in 0..10 {
+            crate::network::test_network::<YataImpl>(seed, 4, 200, Default::default());
+        }
+    }
+
+    #[test]
+    fn duplicates_are_ignored() {
+        for seed in 0..20 {
+            crate::test::test_duplicates::<YataImpl>(seed, 3, 300);
+        }
+    }
+
+    #[test]
+    fn tie_break_policies() {
+        use crate::{
+            dumb_common::peer_id,
+            test::tie,
+            tie_break::{Ascending, Descending, Hashed, Priority},
+        };
+        type Server = Priority<{ peer_id(1) }>;
+        // peer 0 has the greater id, but peer 1 wins the tie
+        assert_eq!(
+            tie::<YataImpl<Server>>(0, 1),
+            tie::<YataImpl<Descending>>(0, 1)
+        );
+        assert_ne!(
+            tie::<YataImpl<Server>>(0, 1),
+            tie::<YataImpl<Ascending>>(0, 1)
+        );
+        for seed in 0..30 {
+            crate::test::test::<YataImpl<Descending>>(seed, 3, 300);
+            crate::test::test::<YataImpl<Server>>(seed, 3, 300);
+            crate::test::test::<YataImpl<Hashed>>(seed, 5, 300);
+            crate::test::test_fork_merge::<YataImpl<Hashed>>(seed, 3, 50);
+        }
+    }
+
     use ctor::ctor;
     #[ctor]
     fn init_color_backtrace() {